@@ -3,7 +3,7 @@ use std::sync::Arc;
 use eyre::ContextCompat;
 use irace_rs::{
     param_space::ParamSpace,
-    scenario::{Scenario, Verbosity},
+    scenario::{ConsoleOutput, Scenario, Verbosity},
     DistributedInstance, Experiment, Run,
 };
 use mahf::{
@@ -62,8 +62,7 @@ where
         + Send
         + Sync,
 {
-    let instance = experiment.instance.wrap_err("missing instance")?;
-    let (problem, evaluator) = instance.unpack();
+    let (problem, evaluator) = experiment.instance.unpack();
 
     let mut params = experiment.params;
 
@@ -142,7 +141,7 @@ fn main() -> ExecResult<()> {
         )
     });
 
-    let result = irace_rs::multi_irace(runs, 8, Some(42))?;
+    let result = irace_rs::multi_irace(runs, 8, Some(42), ConsoleOutput::Inherit)?;
 
     println!("Result: {:?}", result);
     println!("Parameter Space: {:?}", param_space);
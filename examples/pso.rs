@@ -62,8 +62,7 @@ where
         + Send
         + Sync,
 {
-    let instance = experiment.instance.wrap_err("missing instance")?;
-    let (problem, evaluator) = instance.unpack();
+    let (problem, evaluator) = experiment.instance.unpack();
 
     let mut params = experiment.params;
 
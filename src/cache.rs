@@ -0,0 +1,344 @@
+//! Persistent caching of experiment results.
+
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter, Write as _},
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write as _},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use eyre::{bail, WrapErr};
+use mahf::ExecResult;
+
+use crate::{
+    experiment::{Experiment, ParamValue},
+    result::TargetResult,
+    runner::{Instance, TargetRunner},
+    scenario::Scenario,
+};
+
+/// The statistics of a [`ResultCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// The number of experiments whose result was found in the cache.
+    pub hits: u64,
+    /// The number of experiments which had to be executed.
+    pub misses: u64,
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let total = self.hits + self.misses;
+        let rate = if total > 0 {
+            100.0 * self.hits as f64 / total as f64
+        } else {
+            0.0
+        };
+        write!(
+            f,
+            "{} cache hit(s), {} miss(es) ({rate:.1}% hit rate)",
+            self.hits, self.misses
+        )
+    }
+}
+
+/// The cached cost and time of an experiment.
+#[derive(Debug, Clone, Copy)]
+struct CachedResult {
+    cost: f64,
+    time: Option<Duration>,
+}
+
+struct CacheState {
+    entries: HashMap<String, CachedResult>,
+    file: File,
+}
+
+struct CacheInner {
+    path: PathBuf,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// A cache of experiment results, persisted to a local file.
+///
+/// Results are keyed by the configuration values, the name of the instance and the seed
+/// of the experiment, so repeated or resumed tunings with the same instances and seed
+/// reuse the results of identical experiments.
+/// Use [`wrap`] to put the cache in front of a [`TargetRunner`].
+///
+/// Only experiments on named instances are cached, see [`Run::with_instance_names`],
+/// since the index of an instance is not stable if the instances are reordered.
+///
+/// Only the cost and time of successful experiments are cached, metadata is not persisted.
+/// Categorical values are identified by the index of their variant,
+/// i.e. the cache must not be reused after reordering the variants of a parameter.
+///
+/// The file contains one tab-separated line of cost, time (in seconds) and key per result,
+/// and is appended to while tuning.
+///
+/// [`wrap`]: Self::wrap
+/// [`Run::with_instance_names`]: crate::Run::with_instance_names
+#[derive(Clone)]
+pub struct ResultCache {
+    inner: Arc<CacheInner>,
+}
+
+impl ResultCache {
+    /// Opens the cache at `path`, loading all results stored in the file.
+    ///
+    /// The file is created if it does not exist.
+    pub fn open(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .wrap_err_with(|| format!("failed to open result cache {}", path.display()))?;
+
+        let mut entries = HashMap::new();
+        for (index, line) in BufReader::new(&file).lines().enumerate() {
+            let line =
+                line.wrap_err_with(|| format!("failed to read result cache {}", path.display()))?;
+            if line.is_empty() {
+                continue;
+            }
+            let (key, result) = Self::parse_line(&line).wrap_err_with(|| {
+                format!(
+                    "invalid entry in result cache {}:{}",
+                    path.display(),
+                    index + 1
+                )
+            })?;
+            entries.insert(key, result);
+        }
+
+        Ok(Self {
+            inner: Arc::new(CacheInner {
+                path,
+                state: Mutex::new(CacheState { entries, file }),
+                hits: AtomicU64::new(0),
+                misses: AtomicU64::new(0),
+            }),
+        })
+    }
+
+    fn parse_line(line: &str) -> eyre::Result<(String, CachedResult)> {
+        let mut fields = line.splitn(3, '\t');
+        let (Some(cost), Some(time), Some(key)) = (fields.next(), fields.next(), fields.next())
+        else {
+            bail!("expected cost, time and key");
+        };
+
+        let cost = cost.parse().wrap_err("invalid cost")?;
+        let time = if time.is_empty() {
+            None
+        } else {
+            let time = time.parse::<f64>().wrap_err("invalid time")?;
+            Some(Duration::try_from_secs_f64(time).wrap_err("invalid time")?)
+        };
+
+        Ok((key.to_owned(), CachedResult { cost, time }))
+    }
+
+    /// Returns the path of the cache file.
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    /// Returns the number of cached results.
+    pub fn len(&self) -> usize {
+        self.inner.state.lock().unwrap().entries.len()
+    }
+
+    /// Returns if the cache contains no results.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the hit statistics since the cache was opened.
+    ///
+    /// Only experiments executed in this process are counted, i.e. experiments executed in
+    /// forked processes of `irace` are not, see [`Run::with_observer`](crate::Run::with_observer).
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.inner.hits.load(Ordering::Relaxed),
+            misses: self.inner.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Puts the cache in front of the `target_runner`.
+    pub fn wrap<R>(&self, target_runner: R) -> CachedTargetRunner<R> {
+        CachedTargetRunner {
+            target_runner,
+            cache: self.clone(),
+        }
+    }
+
+    /// Returns the cache key of the `experiment`, or `None` if it can't be cached.
+    fn key<I>(experiment: &Experiment<I>) -> Option<String> {
+        // Instances are identified by their name, which is stable under reordering.
+        let instance = format!("{:?}", experiment.instance_name.as_ref()?);
+
+        let mut values: Vec<_> = experiment.values.iter().collect();
+        values.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        let mut key = format!("{instance} {}", experiment.seed);
+        for (name, value) in values {
+            match value {
+                ParamValue::Real(value) => write!(key, " {name}={value}"),
+                ParamValue::Integer(value) => write!(key, " {name}={value}"),
+                ParamValue::Bool(value) => write!(key, " {name}={value}"),
+                ParamValue::Categorical(index) => write!(key, " {name}=#{index}"),
+            }
+            .unwrap();
+        }
+
+        // Keys are stored as last field of a line.
+        (!key.contains(['\n', '\r'])).then_some(key)
+    }
+
+    fn get(&self, key: &str) -> Option<CachedResult> {
+        self.inner.state.lock().unwrap().entries.get(key).copied()
+    }
+
+    fn insert(&self, key: String, result: &TargetResult) -> eyre::Result<()> {
+        let time = result
+            .time
+            .map(|time| time.as_secs_f64().to_string())
+            .unwrap_or_default();
+
+        let mut state = self.inner.state.lock().unwrap();
+        writeln!(state.file, "{}\t{time}\t{key}", result.cost)
+            .and_then(|_| state.file.flush())
+            .wrap_err_with(|| {
+                format!("failed to write result cache {}", self.inner.path.display())
+            })?;
+        state.entries.insert(
+            key,
+            CachedResult {
+                cost: result.cost,
+                time: result.time,
+            },
+        );
+        Ok(())
+    }
+}
+
+/// A [`TargetRunner`] which looks up results in a [`ResultCache`] before executing
+/// the inner target runner.
+///
+/// Results taken from the cache are marked with the `cached` metadata.
+/// Construct it using [`ResultCache::wrap`].
+pub struct CachedTargetRunner<R> {
+    target_runner: R,
+    cache: ResultCache,
+}
+
+impl<R> CachedTargetRunner<R> {
+    /// Returns the cache of the target runner.
+    pub fn cache(&self) -> &ResultCache {
+        &self.cache
+    }
+}
+
+impl<I: Instance, R: TargetRunner<I>> TargetRunner<I> for CachedTargetRunner<R> {
+    fn run(&self, scenario: &Scenario, experiment: Experiment<I>) -> ExecResult<TargetResult> {
+        let Some(key) = ResultCache::key(&experiment) else {
+            return self.target_runner.run(scenario, experiment);
+        };
+
+        if let Some(cached) = self.cache.get(&key) {
+            self.cache.inner.hits.fetch_add(1, Ordering::Relaxed);
+            let mut result = TargetResult::new(cached.cost).with_metadata("cached", true);
+            result.time = cached.time;
+            return Ok(result);
+        }
+        self.cache.inner.misses.fetch_add(1, Ordering::Relaxed);
+
+        let start = Instant::now();
+        let mut result = self.target_runner.run(scenario, experiment)?;
+        result.time.get_or_insert_with(|| start.elapsed());
+        // The result is valid even if it could not be cached.
+        if let Err(e) = self.cache.insert(key, &result) {
+            tracing::warn!("{e:#}");
+        }
+        Ok(result)
+    }
+
+    fn on_tuning_start(&self, scenario: &Scenario) -> eyre::Result<()> {
+        self.target_runner.on_tuning_start(scenario)
+    }
+
+    fn on_tuning_end(&self, scenario: &Scenario) -> eyre::Result<()> {
+        self.target_runner.on_tuning_end(scenario)
+    }
+
+    fn on_iteration_start(&self, scenario: &Scenario, iteration: u32) -> eyre::Result<()> {
+        self.target_runner.on_iteration_start(scenario, iteration)
+    }
+
+    fn on_iteration_end(
+        &self,
+        scenario: &Scenario,
+        iteration: u32,
+        elites: &[String],
+    ) -> eyre::Result<()> {
+        self.target_runner
+            .on_iteration_end(scenario, iteration, elites)
+    }
+
+    fn on_new_instance(&self, scenario: &Scenario, instance: &I) -> eyre::Result<()> {
+        self.target_runner.on_new_instance(scenario, instance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_line_with_time() {
+        let (key, result) = ResultCache::parse_line("1.5\t0.25\t\"a\" 42 x=1").unwrap();
+        assert_eq!(key, "\"a\" 42 x=1");
+        assert_eq!(result.cost, 1.5);
+        assert_eq!(result.time, Some(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn parse_line_without_time() {
+        let (key, result) = ResultCache::parse_line("-3\t\t\"a\" 7").unwrap();
+        assert_eq!(key, "\"a\" 7");
+        assert_eq!(result.cost, -3.0);
+        assert_eq!(result.time, None);
+    }
+
+    #[test]
+    fn parse_line_keeps_tabs_in_key() {
+        let (key, _) = ResultCache::parse_line("1\t\t\"a\tb\" 7").unwrap();
+        assert_eq!(key, "\"a\tb\" 7");
+    }
+
+    #[test]
+    fn parse_line_rejects_invalid_lines() {
+        assert!(ResultCache::parse_line("").is_err());
+        assert!(ResultCache::parse_line("1.5\t0.25").is_err());
+        assert!(ResultCache::parse_line("cost\t\tkey").is_err());
+        assert!(ResultCache::parse_line("1.5\ttime\tkey").is_err());
+    }
+
+    #[test]
+    fn parse_line_rejects_invalid_times() {
+        assert!(ResultCache::parse_line("1.5\t-0.25\tkey").is_err());
+        assert!(ResultCache::parse_line("1.5\tNaN\tkey").is_err());
+        assert!(ResultCache::parse_line("1.5\tinf\tkey").is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::HashMap,
+    ffi::{OsStr, OsString},
+    io::Read,
+    path::PathBuf,
+    process::{Command, Stdio},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use eyre::{bail, ensure, eyre, WrapErr};
+use mahf::ExecResult;
+use regex::Regex;
+use typed_builder::TypedBuilder;
+
+use crate::{
+    experiment::{Experiment, ParamValue},
+    result::TargetResult,
+    runner::{ExperimentTimeout, Instance, TargetRunner},
+    scenario::Scenario,
+};
+
+/// The interval in which a running command is checked for completion and cancellation.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Specifies how the cost and time are parsed from the stdout of a [`CommandRunner`].
+#[derive(Debug, Clone, Default)]
+pub enum OutputFormat {
+    /// The last non-empty line contains the cost, optionally followed by the time in seconds,
+    /// separated by whitespace, as expected by `irace` from a `target-runner` script.
+    #[default]
+    LastLine,
+    /// The last match of the pattern contains the cost in the capture group `cost`,
+    /// and optionally the time in seconds in the capture group `time`.
+    Pattern(Regex),
+}
+
+impl OutputFormat {
+    /// Constructs a new [`OutputFormat::Pattern`] from the regular expression `pattern`.
+    pub fn pattern(pattern: &str) -> eyre::Result<Self> {
+        let regex = Regex::new(pattern).wrap_err("invalid output pattern")?;
+        ensure!(
+            regex.capture_names().flatten().any(|name| name == "cost"),
+            "output pattern requires a `cost` capture group"
+        );
+        Ok(Self::Pattern(regex))
+    }
+
+    /// Parses the cost and optional time from the `stdout` of the command.
+    fn parse(&self, stdout: &str) -> eyre::Result<(f64, Option<Duration>)> {
+        let (cost, time) = match self {
+            OutputFormat::LastLine => {
+                let line = stdout
+                    .lines()
+                    .rev()
+                    .find(|line| !line.trim().is_empty())
+                    .ok_or_else(|| eyre!("command produced no output"))?;
+                let mut fields = line.split_whitespace();
+                let cost = fields.next().unwrap_or_default();
+                (cost, fields.next())
+            }
+            OutputFormat::Pattern(regex) => {
+                let captures = regex
+                    .captures_iter(stdout)
+                    .last()
+                    .ok_or_else(|| eyre!("output does not match pattern {}", regex.as_str()))?;
+                let cost = captures.name("cost").map_or("", |cost| cost.as_str());
+                (cost, captures.name("time").map(|time| time.as_str()))
+            }
+        };
+
+        let cost = cost
+            .parse()
+            .wrap_err_with(|| format!("invalid cost {cost:?}"))?;
+        let time = time
+            .map(|time| {
+                time.parse::<f64>()
+                    .map_err(eyre::Report::new)
+                    .and_then(|secs| Duration::try_from_secs_f64(secs).map_err(eyre::Report::new))
+                    .wrap_err_with(|| format!("invalid time {time:?}"))
+            })
+            .transpose()?;
+        Ok((cost, time))
+    }
+}
+
+/// A [`TargetRunner`] executing an external command, similar to a `target-runner` script of `irace`.
+///
+/// The command is called as
+///
+/// ```text
+/// <program> <args...> <instance_switch><instance> <seed_switch><seed> <switches...>
+/// ```
+///
+/// where each parameter of the configuration is rendered as `<switch><value>`,
+/// using the switch `--<name>=` by default.
+/// A switch ending with whitespace, e.g. `"--alpha "`, passes the value as separate argument.
+/// Categorical values are rendered as the index of the variant, unless [`categories`] are given.
+///
+/// The cost and optional time are parsed from stdout according to the [`OutputFormat`].
+/// A non-zero exit code fails the experiment.
+/// If the experiment is cancelled, e.g. because [`Scenario::experiment_timeout`] expired,
+/// the command is killed.
+///
+/// [`categories`]: Self::categories
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct CommandRunner {
+    /// The executable to run.
+    #[builder(setter(into))]
+    pub program: PathBuf,
+    /// Fixed arguments passed before the instance, seed and configuration.
+    #[builder(default)]
+    pub args: Vec<OsString>,
+    /// The switch to pass the instance with.
+    #[builder(default = "--instance=".to_string(), setter(into))]
+    pub instance_switch: String,
+    /// The switch to pass the seed with.
+    #[builder(default = "--seed=".to_string(), setter(into))]
+    pub seed_switch: String,
+    /// The switches of the parameters by name, if different from `--<name>=`.
+    #[builder(default)]
+    pub switches: HashMap<String, String>,
+    /// The command-line values of the variants of categorical parameters by name.
+    #[builder(default)]
+    pub categories: HashMap<String, Vec<String>>,
+    /// The format of the output.
+    #[builder(default)]
+    pub output: OutputFormat,
+    /// The working directory of the command.
+    #[builder(default, setter(strip_option, into))]
+    pub working_dir: Option<PathBuf>,
+}
+
+/// Appends `<switch><value>` to the arguments, or `<switch> <value>` if the switch ends
+/// with whitespace.
+fn push_switch(args: &mut Vec<OsString>, switch: &str, value: impl AsRef<OsStr>) {
+    let trimmed = switch.trim_end();
+    if trimmed.len() < switch.len() {
+        args.push(trimmed.into());
+        args.push(value.as_ref().to_owned());
+    } else {
+        let mut arg = OsString::from(switch);
+        arg.push(value);
+        args.push(arg);
+    }
+}
+
+/// Reads the pipe to a string on a separate thread, which prevents the command from blocking
+/// on a full pipe.
+fn read_to_string(mut pipe: impl Read + Send + 'static) -> JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = pipe.read_to_end(&mut output);
+        String::from_utf8_lossy(&output).into_owned()
+    })
+}
+
+impl CommandRunner {
+    /// Renders the arguments of the command for the `experiment`.
+    fn render_args<I: AsRef<OsStr>>(
+        &self,
+        experiment: &Experiment<I>,
+    ) -> ExecResult<Vec<OsString>> {
+        let mut args = self.args.clone();
+        push_switch(&mut args, &self.instance_switch, experiment.instance);
+        push_switch(&mut args, &self.seed_switch, experiment.seed.to_string());
+
+        for (name, value) in &experiment.values {
+            let value = match value {
+                ParamValue::Real(value) => value.to_string(),
+                ParamValue::Integer(value) => value.to_string(),
+                ParamValue::Bool(value) => value.to_string(),
+                ParamValue::Categorical(index) => match self.categories.get(name) {
+                    Some(labels) => labels.get(*index).cloned().ok_or_else(|| {
+                        eyre!("no command-line value for variant {index} of parameter {name}")
+                    })?,
+                    None => index.to_string(),
+                },
+            };
+            match self.switches.get(name) {
+                Some(switch) => push_switch(&mut args, switch, value),
+                None => push_switch(&mut args, &format!("--{name}="), value),
+            }
+        }
+
+        Ok(args)
+    }
+}
+
+impl<I: Instance + AsRef<OsStr>> TargetRunner<I> for CommandRunner {
+    fn run(&self, scenario: &Scenario, experiment: Experiment<I>) -> ExecResult<TargetResult> {
+        let mut command = Command::new(&self.program);
+        command
+            .args(self.render_args(&experiment)?)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if let Some(working_dir) = &self.working_dir {
+            command.current_dir(working_dir);
+        }
+
+        let start = Instant::now();
+        let mut child = command
+            .spawn()
+            .wrap_err_with(|| format!("failed to execute {}", self.program.display()))?;
+        let stdout = read_to_string(child.stdout.take().expect("stdout is piped"));
+        let stderr = read_to_string(child.stderr.take().expect("stderr is piped"));
+
+        let status = loop {
+            if let Some(status) = child.try_wait().wrap_err("failed to wait for command")? {
+                break status;
+            }
+            if experiment.is_cancelled() {
+                // The command may have exited in the meantime.
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(ExperimentTimeout {
+                    timeout: scenario.experiment_timeout.unwrap_or_default(),
+                }
+                .into());
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        };
+        let elapsed = start.elapsed();
+
+        let stdout = stdout.join().unwrap_or_default();
+        let stderr = stderr.join().unwrap_or_default();
+        if !status.success() {
+            bail!(
+                "{} failed with {status}: {}",
+                self.program.display(),
+                stderr.trim()
+            );
+        }
+
+        let (cost, time) = self
+            .output
+            .parse(&stdout)
+            .wrap_err_with(|| format!("failed to parse output of {}", self.program.display()))?;
+        Ok(TargetResult::new(cost).with_time(time.unwrap_or(elapsed)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_line_with_cost_and_time() {
+        let output = "progress 1\nprogress 2\n12.5 0.75\n\n";
+        let (cost, time) = OutputFormat::LastLine.parse(output).unwrap();
+        assert_eq!(cost, 12.5);
+        assert_eq!(time, Some(Duration::from_millis(750)));
+    }
+
+    #[test]
+    fn last_line_with_cost_only() {
+        let (cost, time) = OutputFormat::LastLine.parse("-3e2\n").unwrap();
+        assert_eq!(cost, -300.0);
+        assert_eq!(time, None);
+    }
+
+    #[test]
+    fn last_line_rejects_invalid_output() {
+        assert!(OutputFormat::LastLine.parse("").is_err());
+        assert!(OutputFormat::LastLine.parse(" \n\t\n").is_err());
+        assert!(OutputFormat::LastLine.parse("cost\n").is_err());
+        assert!(OutputFormat::LastLine.parse("1.0 time\n").is_err());
+        assert!(OutputFormat::LastLine.parse("1.0 -1\n").is_err());
+    }
+
+    #[test]
+    fn pattern_uses_last_match() {
+        let format = OutputFormat::pattern(r"cost=(?P<cost>\S+)( time=(?P<time>\S+))?").unwrap();
+        let output = "cost=5 time=1\nother\ncost=4 time=2\ndone\n";
+        let (cost, time) = format.parse(output).unwrap();
+        assert_eq!(cost, 4.0);
+        assert_eq!(time, Some(Duration::from_secs(2)));
+
+        let (cost, time) = format.parse("cost=0.5\n").unwrap();
+        assert_eq!(cost, 0.5);
+        assert_eq!(time, None);
+    }
+
+    #[test]
+    fn pattern_rejects_invalid_output() {
+        let format = OutputFormat::pattern(r"cost=(?P<cost>\S+)").unwrap();
+        assert!(format.parse("no result\n").is_err());
+        assert!(format.parse("cost=nan?\n").is_err());
+    }
+
+    #[test]
+    fn pattern_requires_cost_group() {
+        assert!(OutputFormat::pattern(r"result=(?P<value>\S+)").is_err());
+        assert!(OutputFormat::pattern(r"(").is_err());
+    }
+}
@@ -0,0 +1,183 @@
+//! Capturing the console output of `irace`.
+
+use std::{fs::File, io::Write, sync::Mutex};
+
+use eyre::WrapErr;
+use pyo3::prelude::*;
+
+use crate::scenario::ConsoleOutput;
+
+/// The console stream a [`ConsoleWriter`] replaces.
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn name(self) -> &'static str {
+        match self {
+            Stream::Stdout => "stdout",
+            Stream::Stderr => "stderr",
+        }
+    }
+
+    /// Logs a complete `line` written to the stream.
+    ///
+    /// Output on stdout is logged as info, output on stderr as warning,
+    /// or as error if it is an R error message.
+    fn log_line(self, line: &str) {
+        let line = line.trim_end();
+        if line.is_empty() {
+            return;
+        }
+
+        match self {
+            Stream::Stdout => tracing::info!(target: "irace", "{line}"),
+            Stream::Stderr if line.starts_with("Error") => {
+                tracing::error!(target: "irace", "{line}")
+            }
+            Stream::Stderr => tracing::warn!(target: "irace", "{line}"),
+        }
+    }
+}
+
+/// Where a [`ConsoleWriter`] forwards the output to.
+enum Sink {
+    /// Log complete lines, buffering the current partial line.
+    Log {
+        buffer: String,
+    },
+    File(File),
+}
+
+/// A Python file-like object replacing `sys.stdout` or `sys.stderr`,
+/// see [`ConsoleOutput`].
+#[pyclass]
+pub(crate) struct ConsoleWriter {
+    stream: Stream,
+    sink: Mutex<Sink>,
+}
+
+impl ConsoleWriter {
+    /// Writes the remaining partial line.
+    fn finish(&self) {
+        if let Sink::Log { buffer } = &mut *self.sink.lock().unwrap() {
+            self.stream.log_line(buffer);
+            buffer.clear();
+        }
+    }
+}
+
+#[pymethods]
+impl ConsoleWriter {
+    fn write(&self, text: &str) -> PyResult<usize> {
+        match &mut *self.sink.lock().unwrap() {
+            Sink::Log { buffer } => {
+                buffer.push_str(text);
+                if let Some(end) = buffer.rfind('\n') {
+                    buffer[..end]
+                        .lines()
+                        .for_each(|line| self.stream.log_line(line));
+                    buffer.drain(..=end);
+                }
+            }
+            Sink::File(file) => file.write_all(text.as_bytes())?,
+        }
+        // Python expects the number of characters written.
+        Ok(text.chars().count())
+    }
+
+    fn flush(&self) -> PyResult<()> {
+        if let Sink::File(file) = &mut *self.sink.lock().unwrap() {
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    fn isatty(&self) -> bool {
+        false
+    }
+
+    #[getter]
+    fn name(&self) -> String {
+        format!("<irace {}>", self.stream.name())
+    }
+
+    #[getter]
+    fn encoding(&self) -> &'static str {
+        "utf-8"
+    }
+}
+
+/// Replaces the Python `sys.stdout` and `sys.stderr` while `irace` is running,
+/// which also captures the output of R.
+///
+/// Output written directly to the file descriptors, e.g. by native code, is not captured.
+pub(crate) struct ConsoleCapture {
+    /// The original `sys.stdout` and `sys.stderr`.
+    original: (PyObject, PyObject),
+    writers: [Py<ConsoleWriter>; 2],
+}
+
+impl ConsoleCapture {
+    /// Replaces the console streams according to `output`,
+    /// or returns `None` if the output is inherited.
+    pub fn install(py: Python, output: &ConsoleOutput) -> eyre::Result<Option<Self>> {
+        let (stdout, stderr) = match output {
+            ConsoleOutput::Inherit => return Ok(None),
+            ConsoleOutput::Log => (
+                Sink::Log {
+                    buffer: String::new(),
+                },
+                Sink::Log {
+                    buffer: String::new(),
+                },
+            ),
+            ConsoleOutput::File(path) => {
+                let file = File::create(path)
+                    .wrap_err_with(|| format!("failed to create console log {}", path.display()))?;
+                // Both streams share the file offset, i.e. their output is interleaved.
+                let stderr = file.try_clone()?;
+                (Sink::File(file), Sink::File(stderr))
+            }
+        };
+
+        let sys = py.import("sys")?;
+        let original = (
+            sys.getattr("stdout")?.to_object(py),
+            sys.getattr("stderr")?.to_object(py),
+        );
+        let writer = |stream, sink| {
+            Py::new(
+                py,
+                ConsoleWriter {
+                    stream,
+                    sink: Mutex::new(sink),
+                },
+            )
+        };
+        let writers = [
+            writer(Stream::Stdout, stdout)?,
+            writer(Stream::Stderr, stderr)?,
+        ];
+
+        sys.setattr("stdout", writers[0].clone_ref(py))?;
+        sys.setattr("stderr", writers[1].clone_ref(py))?;
+        Ok(Some(Self { original, writers }))
+    }
+
+    /// Restores the original console streams, writing the remaining partial lines.
+    pub fn restore(self, py: Python) -> eyre::Result<()> {
+        let sys = py.import("sys")?;
+        sys.setattr("stdout", self.original.0)?;
+        sys.setattr("stderr", self.original.1)?;
+
+        for writer in &self.writers {
+            let writer = writer.borrow(py);
+            writer.finish();
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
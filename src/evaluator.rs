@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+use eyre::eyre;
+use indexmap::IndexMap;
+use mahf::ExecResult;
+
+use crate::scenario::Scenario;
+
+/// The results of all configurations executed on an instance.
+///
+/// An instance is identified by its `instance_id` and `seed`, as passed by `irace`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstanceResults {
+    pub instance_id: String,
+    pub seed: u64,
+    /// The cost returned by the target runner for each configuration id in the race,
+    /// in execution order.
+    pub costs: IndexMap<String, f64>,
+}
+
+impl InstanceResults {
+    pub(crate) fn new(instance_id: String, seed: u64) -> Self {
+        Self {
+            instance_id,
+            seed,
+            costs: IndexMap::new(),
+        }
+    }
+
+    /// Returns the best (lowest) cost of all configurations, if any.
+    pub fn best_cost(&self) -> Option<f64> {
+        self.costs.values().copied().reduce(f64::min)
+    }
+}
+
+/// Trait representing a target evaluator.
+///
+/// The target evaluator is called after all configurations of a race step have been
+/// executed on an instance, and computes the cost reported to `irace` for a configuration
+/// from the [`InstanceResults`] of all configurations, e.g. to normalize the costs.
+/// See [`RelativeDeviation`] for a built-in target evaluator.
+///
+/// A panic inside the target evaluator is treated like an error returned by it.
+pub trait TargetEvaluator: Send + Sync + 'static {
+    fn evaluate(
+        &self,
+        scenario: &Scenario,
+        configuration_id: &str,
+        results: &InstanceResults,
+    ) -> ExecResult<f64>;
+}
+
+impl TargetEvaluator for Box<dyn TargetEvaluator> {
+    fn evaluate(
+        &self,
+        scenario: &Scenario,
+        configuration_id: &str,
+        results: &InstanceResults,
+    ) -> ExecResult<f64> {
+        (**self).evaluate(scenario, configuration_id, results)
+    }
+}
+
+impl<F> TargetEvaluator for F
+where
+    F: Fn(&Scenario, &str, &InstanceResults) -> ExecResult<f64> + Send + Sync + 'static,
+{
+    fn evaluate(
+        &self,
+        scenario: &Scenario,
+        configuration_id: &str,
+        results: &InstanceResults,
+    ) -> ExecResult<f64> {
+        (self)(scenario, configuration_id, results)
+    }
+}
+
+/// A [`TargetEvaluator`] which reports the relative deviation of the cost from the best cost
+/// on the instance, i.e. `(cost - best) / |best|`.
+///
+/// The best cost is the best known cost of the instance, if given, or the best cost of all
+/// configurations in the race on the instance.
+/// If the best cost is zero, the absolute deviation is reported instead.
+///
+/// Only the cost reported to `irace` is normalized: observers and the [`ExperimentLog`]
+/// receive the raw cost returned by the target runner.
+///
+/// [`ExperimentLog`]: crate::ExperimentLog
+#[derive(Debug, Clone, Default)]
+pub struct RelativeDeviation {
+    best_known: HashMap<String, f64>,
+}
+
+impl RelativeDeviation {
+    /// Constructs a new `RelativeDeviation` from the best cost of all configurations.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs a new `RelativeDeviation` from the best known cost of each instance id.
+    ///
+    /// Instances without best known cost use the best cost of all configurations.
+    pub fn with_best_known(best_known: impl IntoIterator<Item = (String, f64)>) -> Self {
+        Self {
+            best_known: best_known.into_iter().collect(),
+        }
+    }
+}
+
+impl TargetEvaluator for RelativeDeviation {
+    fn evaluate(
+        &self,
+        _scenario: &Scenario,
+        configuration_id: &str,
+        results: &InstanceResults,
+    ) -> ExecResult<f64> {
+        let cost = *results.costs.get(configuration_id).ok_or_else(|| {
+            eyre!(
+                "no result of configuration {configuration_id} on instance {}",
+                results.instance_id
+            )
+        })?;
+
+        let best_known = self.best_known.get(&results.instance_id).copied();
+        let best = match (best_known, results.best_cost()) {
+            (Some(known), Some(observed)) => known.min(observed),
+            (known, observed) => known.or(observed).unwrap_or(cost),
+        };
+
+        let deviation = cost - best;
+        Ok(if best == 0.0 {
+            deviation
+        } else {
+            deviation / best.abs()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(costs: &[(&str, f64)]) -> InstanceResults {
+        let mut results = InstanceResults::new("instance".to_owned(), 0);
+        for &(configuration_id, cost) in costs {
+            results.costs.insert(configuration_id.to_owned(), cost);
+        }
+        results
+    }
+
+    fn evaluate(evaluator: &RelativeDeviation, results: &InstanceResults, id: &str) -> f64 {
+        evaluator
+            .evaluate(&Scenario::builder().build(), id, results)
+            .unwrap()
+    }
+
+    #[test]
+    fn relative_deviation_normalizes_by_best_cost() {
+        let evaluator = RelativeDeviation::new();
+        let results = results(&[("1", 12.0), ("2", 10.0), ("3", 15.0)]);
+        assert_eq!(evaluate(&evaluator, &results, "1"), 0.2);
+        assert_eq!(evaluate(&evaluator, &results, "2"), 0.0);
+        assert_eq!(evaluate(&evaluator, &results, "3"), 0.5);
+    }
+
+    #[test]
+    fn relative_deviation_handles_zero_and_negative_best_costs() {
+        let evaluator = RelativeDeviation::new();
+        let zero = results(&[("1", 0.0), ("2", 3.0)]);
+        assert_eq!(evaluate(&evaluator, &zero, "2"), 3.0);
+
+        let negative = results(&[("1", -10.0), ("2", -5.0)]);
+        assert_eq!(evaluate(&evaluator, &negative, "1"), 0.0);
+        assert_eq!(evaluate(&evaluator, &negative, "2"), 0.5);
+    }
+
+    #[test]
+    fn relative_deviation_uses_best_known_costs() {
+        let evaluator = RelativeDeviation::with_best_known([("instance".to_owned(), 8.0)]);
+        let results = results(&[("1", 10.0), ("2", 12.0)]);
+        assert_eq!(evaluate(&evaluator, &results, "1"), 0.25);
+
+        // Costs better than the best known cost are used instead.
+        let evaluator = RelativeDeviation::with_best_known([("instance".to_owned(), 11.0)]);
+        assert_eq!(evaluate(&evaluator, &results, "2"), 0.2);
+    }
+
+    #[test]
+    fn relative_deviation_falls_back_for_unseen_instances() {
+        let evaluator = RelativeDeviation::with_best_known([("other".to_owned(), 1.0)]);
+        let results = results(&[("1", 10.0), ("2", 12.0)]);
+        assert_eq!(evaluate(&evaluator, &results, "2"), 0.2);
+    }
+
+    #[test]
+    fn relative_deviation_rejects_unknown_configurations() {
+        let evaluator = RelativeDeviation::new();
+        let results = results(&[("1", 10.0)]);
+        assert!(evaluator
+            .evaluate(&Scenario::builder().build(), "2", &results)
+            .is_err());
+    }
+}
@@ -1,9 +1,23 @@
+use std::{
+    collections::HashMap,
+    fmt::{Display, Formatter},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Once,
+    },
+    time::Instant,
+};
+
+use eyre::WrapErr;
+use indexmap::IndexMap;
 use mahf::params::Params;
-use pyo3::{exceptions::PyValueError, types::PyDict, PyAny, PyResult};
+use pyo3::{exceptions::PyValueError, types::PyDict, FromPyObject, PyAny, PyResult};
+use tracing::Span;
 
 use crate::{
     param_space::{ParamSpace, ParamSubspace},
-    runner::ErasedInstance,
+    runner::Instance,
+    source::ErasedInstances,
 };
 
 pub(crate) trait FromPyDict<'source>: Sized {
@@ -11,9 +25,23 @@ pub(crate) trait FromPyDict<'source>: Sized {
     fn from_dict(ob: &'source PyDict, space: &ParamSpace) -> PyResult<Self>;
 }
 
-impl<'a> FromPyDict<'a> for Params {
+/// A single parameter value as passed by `irace`.
+///
+/// Categorical values are represented by the index of the variant.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamValue {
+    Real(f64),
+    Integer(u32),
+    Bool(bool),
+    Categorical(usize),
+}
+
+/// The parameter values of a configuration, in the order passed by `irace`.
+pub type ParamValues = IndexMap<String, ParamValue>;
+
+impl<'a> FromPyDict<'a> for ParamValues {
     fn from_dict(kwargs: &'a PyDict, param_space: &ParamSpace) -> PyResult<Self> {
-        let mut params = Params::new();
+        let mut values = ParamValues::new();
 
         for (py_key, py_value) in kwargs {
             let key = py_key.extract::<String>()?;
@@ -22,65 +50,391 @@ impl<'a> FromPyDict<'a> for Params {
                 .get_raw(&key)
                 .ok_or_else(|| PyValueError::new_err(format!("unknown parameter name: {}", key)))?;
 
-            match subspace {
-                ParamSubspace::Real(_) => params.insert(key, py_value.extract::<f64>()?),
-                ParamSubspace::Integer(_) => params.insert(key, py_value.extract::<u32>()?),
-                ParamSubspace::Bool(_) => params.insert(key, py_value.extract::<bool>()?),
+            let value = match subspace {
+                ParamSubspace::Real(_) => ParamValue::Real(py_value.extract::<f64>()?),
+                ParamSubspace::Integer(_) => ParamValue::Integer(py_value.extract::<u32>()?),
+                ParamSubspace::Bool(_) => ParamValue::Bool(py_value.extract::<bool>()?),
                 ParamSubspace::Categorical(categorical) => {
                     let index = py_value.extract::<usize>()?;
-                    params.insert_raw(key, categorical.variants[index].clone());
+                    if index >= categorical.variants.len() {
+                        return Err(PyValueError::new_err(format!(
+                            "invalid variant index {index} for parameter {key}"
+                        )));
+                    }
+                    ParamValue::Categorical(index)
                 }
                 ParamSubspace::Nested(_) => {
                     return Err(PyValueError::new_err(
                         "nested parameter space not supported",
                     ))
                 }
+            };
+            values.insert(key, value);
+        }
+
+        Ok(values)
+    }
+}
+
+impl<'a> FromPyDict<'a> for Params {
+    fn from_dict(kwargs: &'a PyDict, param_space: &ParamSpace) -> PyResult<Self> {
+        let values = ParamValues::from_dict(kwargs, param_space)?;
+        Ok(to_params(&values, param_space))
+    }
+}
+
+/// Converts the parameter `values` into [`Params`].
+///
+/// The values are expected to be valid for the `param_space`.
+pub(crate) fn to_params(values: &ParamValues, param_space: &ParamSpace) -> Params {
+    let mut params = Params::new();
+
+    for (key, value) in values {
+        let key = key.clone();
+        match (value, param_space.get_raw(&key)) {
+            (ParamValue::Real(value), _) => params.insert(key, *value),
+            (ParamValue::Integer(value), _) => params.insert(key, *value),
+            (ParamValue::Bool(value), _) => params.insert(key, *value),
+            (ParamValue::Categorical(index), Some(ParamSubspace::Categorical(categorical))) => {
+                params.insert_raw(key, categorical.variants[*index].clone());
+            }
+            (ParamValue::Categorical(_), _) => {
+                unreachable!("categorical value for non-categorical parameter {key}")
             }
         }
+    }
+
+    params
+}
+
+/// A token signalling that an [`Experiment`] should be cancelled.
+///
+/// The token is cancelled when the time limit of the experiment expires,
+/// see [`Scenario::experiment_timeout`].
+/// Long-running target runners should poll [`is_cancelled`] and return early.
+///
+/// [`Scenario::experiment_timeout`]: crate::scenario::Scenario::experiment_timeout
+/// [`is_cancelled`]: Self::is_cancelled
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    /// Constructs a new `CancellationToken` without deadline.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Constructs a new `CancellationToken` which is cancelled after the `deadline`.
+    pub fn with_deadline(deadline: Instant) -> Self {
+        Self {
+            cancelled: Default::default(),
+            deadline: Some(deadline),
+        }
+    }
 
-        Ok(params)
+    /// Returns the deadline of the experiment, if any.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Cancels the experiment.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns if the experiment was cancelled or its deadline has passed.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
     }
 }
 
-/// An experiment, i.e. single execution of the [`TargetRunner`].
+/// The state of the race an [`Experiment`] is executed in.
 ///
-/// The experiment specifies the parameters, seed and problem instance
-/// to execute the target algorithm with.
+/// Each field is only available if provided by the Python wrapper of `irace`,
+/// as the following attributes of the experiment object passed to the target runner:
 ///
-/// [`TargetRunner`]: crate::TargetRunner
-pub struct Experiment<'a, I> {
+/// | Attribute       | Type   | Field                                  |
+/// |-----------------|--------|----------------------------------------|
+/// | `iteration`     | `int`  | [`iteration`](Self::iteration)         |
+/// | `race_step`     | `int`  | [`step`](Self::step)                   |
+/// | `num_evaluated` | `int`  | [`num_evaluated`](Self::num_evaluated) |
+/// | `is_elite`      | `bool` | [`is_elite`](Self::is_elite)           |
+/// | `parent_id`     | `str`  | [`parent_id`](Self::parent_id)         |
+///
+/// The iteration hooks of the target runner and the race events of observers require
+/// `iteration` and `num_evaluated`. A warning is logged once if any attribute is missing.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RaceContext {
+    /// The iteration of `irace`, starting at 1.
+    pub iteration: Option<u32>,
+    /// The step of the race within the iteration, starting at 1.
+    pub step: Option<u32>,
+    /// The number of instances the configuration has already been evaluated on.
+    pub num_evaluated: Option<u32>,
+    /// If the configuration is an elite of a previous iteration.
+    pub is_elite: Option<bool>,
+    /// The id of the configuration the configuration was sampled from.
+    pub parent_id: Option<String>,
+}
+
+impl RaceContext {
+    /// The attributes of the experiment object the context is extracted from.
+    const ATTRIBUTES: [&'static str; 5] = [
+        "iteration",
+        "race_step",
+        "num_evaluated",
+        "is_elite",
+        "parent_id",
+    ];
+
+    fn from_py(obj: &PyAny) -> PyResult<Self> {
+        static MISSING_REPORTED: Once = Once::new();
+        if !MISSING_REPORTED.is_completed() {
+            let missing = Self::ATTRIBUTES
+                .into_iter()
+                .filter(|name| !obj.hasattr(*name).unwrap_or(false))
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                MISSING_REPORTED.call_once(|| {
+                    tracing::warn!(
+                        ?missing,
+                        "the Python wrapper of `irace` does not provide the race context, \
+                         iteration hooks and race events are limited"
+                    );
+                });
+            }
+        }
+
+        Ok(Self {
+            iteration: optional_attr(obj, "iteration")?,
+            step: optional_attr(obj, "race_step")?,
+            num_evaluated: optional_attr(obj, "num_evaluated")?,
+            is_elite: optional_attr(obj, "is_elite")?,
+            parent_id: optional_attr(obj, "parent_id")?,
+        })
+    }
+}
+
+/// Extracts the attribute `name` of `obj`, or `None` if it does not exist or is `None`.
+fn optional_attr<'a, T: FromPyObject<'a>>(obj: &'a PyAny, name: &str) -> PyResult<Option<T>> {
+    if !obj.hasattr(name)? {
+        return Ok(None);
+    }
+    obj.getattr(name)?.extract()
+}
+
+/// The data of an experiment as passed by `irace`, independent of Python.
+#[derive(Debug, Clone)]
+pub(crate) struct ExperimentData {
     pub id: String,
     pub seed: u64,
     pub instance_id: Option<String>,
-    pub instance: Option<&'a I>,
-    pub params: Params,
+    pub instance_index: Option<usize>,
+    pub instance_name: Option<String>,
+    pub values: ParamValues,
+    pub context: RaceContext,
 }
 
-impl<'a, I: 'static> Experiment<'a, I> {
+impl ExperimentData {
+    /// Extracts the experiment data from the Python `obj`.
+    ///
+    /// If the instances are named, `irace` passes the instance names, which are resolved
+    /// using `instance_indices`, and the name is used as instance id.
     pub(crate) fn from_py(
         obj: &PyAny,
-        instances: &'a [Box<dyn ErasedInstance>],
         param_space: &ParamSpace,
+        instance_indices: &HashMap<String, usize>,
     ) -> PyResult<Self> {
         let id = obj.getattr("configuration_id")?.extract::<String>()?;
         let seed = obj.getattr("seed")?.extract::<u64>()?;
 
-        let instance_id = obj.getattr("instance_id")?.extract::<Option<String>>()?;
-        let instance = obj
-            .getattr("instance")?
-            .extract::<Option<usize>>()?
-            .and_then(|index| instances.get(index))
-            .and_then(|instance| instance.as_ref().as_any().downcast_ref());
+        let mut instance_id = obj.getattr("instance_id")?.extract::<Option<String>>()?;
+        let instance = obj.getattr("instance")?;
+        let (instance_index, instance_name) = if instance.is_none() {
+            (None, None)
+        } else if let Ok(name) = instance.extract::<String>() {
+            let index = *instance_indices
+                .get(&name)
+                .ok_or_else(|| PyValueError::new_err(format!("unknown instance name: {name}")))?;
+            instance_id = Some(name.clone());
+            (Some(index), Some(name))
+        } else {
+            (Some(instance.extract::<usize>()?), None)
+        };
 
         let params_dict = obj.getattr("configuration")?.downcast::<PyDict>()?;
-        let params = Params::from_dict(params_dict, param_space)?;
+        let values = ParamValues::from_dict(params_dict, param_space)?;
+        let context = RaceContext::from_py(obj)?;
 
         Ok(Self {
             id,
-            instance_id,
             seed,
-            instance,
-            params,
+            instance_id,
+            instance_index,
+            instance_name,
+            values,
+            context,
         })
     }
 }
+
+/// Error representing an experiment whose instance could not be resolved.
+///
+/// Unlike failures of the instance source, these errors always abort the tuning,
+/// regardless of the [`FailurePolicy`](crate::scenario::FailurePolicy) of the scenario.
+#[derive(Debug)]
+pub(crate) enum InstanceError {
+    /// `irace` passed no instance.
+    Missing { configuration_id: String },
+    /// The instance index is out of range.
+    OutOfRange { index: usize, num_instances: usize },
+    /// The instance is not of the type expected by the target runner.
+    TypeMismatch {
+        index: usize,
+        expected: &'static str,
+    },
+}
+
+impl Display for InstanceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstanceError::Missing { configuration_id } => write!(
+                f,
+                "experiment of configuration {configuration_id} has no instance"
+            ),
+            InstanceError::OutOfRange {
+                index,
+                num_instances,
+            } => write!(
+                f,
+                "instance index {index} is out of range for {num_instances} instance(s)"
+            ),
+            InstanceError::TypeMismatch { index, expected } => {
+                write!(
+                    f,
+                    "instance {index} is not of the expected type `{expected}`"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for InstanceError {}
+
+/// An experiment, i.e. single execution of the [`TargetRunner`].
+///
+/// The experiment specifies the parameters, seed and problem instance
+/// to execute the target algorithm with.
+///
+/// [`TargetRunner`]: crate::TargetRunner
+pub struct Experiment<'a, I> {
+    pub id: String,
+    pub seed: u64,
+    pub instance_id: Option<String>,
+    pub instance: &'a I,
+    /// The index of the instance in the instances of the run.
+    pub instance_index: usize,
+    /// The stable name of the instance, if the instances are named.
+    ///
+    /// The name is also used as [`instance_id`](Self::instance_id).
+    pub instance_name: Option<String>,
+    pub params: Params,
+    /// The raw parameter values of the configuration, e.g. to identify it.
+    pub values: ParamValues,
+    /// Signals that the experiment should be cancelled, e.g. because its time limit expired.
+    pub cancellation: CancellationToken,
+    /// The state of the race the experiment is executed in.
+    pub context: RaceContext,
+}
+
+/// Returns a new span for an experiment, carrying its configuration id, instance id and seed.
+pub(crate) fn experiment_span(
+    parent: &Span,
+    configuration_id: &str,
+    instance_id: Option<&str>,
+    seed: u64,
+) -> Span {
+    tracing::info_span!(
+        parent: parent,
+        "experiment",
+        configuration_id,
+        instance_id,
+        seed
+    )
+}
+
+/// Loads the instance of the experiment `data` from the `instances`.
+///
+/// Fails if the instance index is missing or out of range, the instance can't be loaded,
+/// or it is not of type `I`.
+pub(crate) fn load_instance<I: Instance>(
+    data: &ExperimentData,
+    instances: &dyn ErasedInstances,
+) -> eyre::Result<Arc<I>> {
+    let index = data.instance_index.ok_or_else(|| InstanceError::Missing {
+        configuration_id: data.id.clone(),
+    })?;
+    load_instance_at(index, instances)
+}
+
+/// Loads the instance at `index` from the `instances`.
+///
+/// Fails if the index is out of range, the instance can't be loaded, or it is not of type `I`.
+pub(crate) fn load_instance_at<I: Instance>(
+    index: usize,
+    instances: &dyn ErasedInstances,
+) -> eyre::Result<Arc<I>> {
+    if index >= instances.len() {
+        return Err(InstanceError::OutOfRange {
+            index,
+            num_instances: instances.len(),
+        }
+        .into());
+    }
+
+    instances
+        .load(index)
+        .wrap_err_with(|| format!("failed to load instance {index}"))?
+        .downcast()
+        .map_err(|_| {
+            InstanceError::TypeMismatch {
+                index,
+                expected: std::any::type_name::<I>(),
+            }
+            .into()
+        })
+}
+
+impl<'a, I> Experiment<'a, I> {
+    /// Constructs the experiment from the `data` passed by `irace` and its loaded `instance`.
+    pub(crate) fn from_data(
+        data: &ExperimentData,
+        instance: &'a I,
+        param_space: &ParamSpace,
+        cancellation: CancellationToken,
+    ) -> Self {
+        Self {
+            id: data.id.clone(),
+            seed: data.seed,
+            instance_id: data.instance_id.clone(),
+            instance,
+            instance_index: data.instance_index.unwrap_or_default(),
+            instance_name: data.instance_name.clone(),
+            params: to_params(&data.values, param_space),
+            values: data.values.clone(),
+            cancellation,
+            context: data.context.clone(),
+        }
+    }
+
+    /// Returns if the experiment was cancelled, e.g. because its time limit expired.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+}
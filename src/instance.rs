@@ -1,14 +1,15 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 
+use indexmap::IndexMap;
 use mahf::{problems::Evaluate, Problem};
 
-pub trait EvaluateDistributed: Evaluate + dyn_clone::DynClone {
+pub trait EvaluateDistributed: Evaluate + dyn_clone::DynClone + Send + Sync {
     fn into_evaluate(self: Box<Self>) -> Box<dyn Evaluate<Problem = Self::Problem>>;
 }
 
 impl<T> EvaluateDistributed for T
 where
-    T: Evaluate + Clone + 'static,
+    T: Evaluate + Clone + Send + Sync + 'static,
 {
     fn into_evaluate(self: Box<T>) -> Box<dyn Evaluate<Problem = Self::Problem>> {
         self
@@ -59,3 +60,69 @@ impl<P: Problem> Clone for DistributedInstance<P> {
         }
     }
 }
+
+/// A problem instance with a stable name.
+///
+/// The name identifies the instance towards `irace` instead of its index,
+/// see [`Run::with_named_instances`](crate::Run::with_named_instances).
+pub trait NamedInstance {
+    fn name(&self) -> String;
+}
+
+impl<P: Problem> NamedInstance for DistributedInstance<P> {
+    fn name(&self) -> String {
+        self.problem().name().to_owned()
+    }
+}
+
+impl NamedInstance for String {
+    fn name(&self) -> String {
+        self.clone()
+    }
+}
+
+impl NamedInstance for PathBuf {
+    fn name(&self) -> String {
+        self.to_string_lossy().into_owned()
+    }
+}
+
+/// Features of a problem instance, e.g. its size, class or difficulty.
+///
+/// The class is used to stratify the instance order, see [`Run::stratified`],
+/// and to break down the results per class.
+/// Instance sets can be filtered by their features, see [`Run::filter_instances`].
+///
+/// [`Run::stratified`]: crate::Run::stratified
+/// [`Run::filter_instances`]: crate::Run::filter_instances
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstanceFeatures {
+    /// The class of the instance.
+    pub class: Option<String>,
+    /// Numeric features by name.
+    pub values: IndexMap<String, f64>,
+}
+
+impl InstanceFeatures {
+    /// Constructs new `InstanceFeatures` without class and features.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the `class` of the instance.
+    pub fn with_class(mut self, class: impl Into<String>) -> Self {
+        self.class = Some(class.into());
+        self
+    }
+
+    /// Adds the numeric feature `value` under the given `name`.
+    pub fn with_value(mut self, name: impl Into<String>, value: f64) -> Self {
+        self.values.insert(name.into(), value);
+        self
+    }
+
+    /// Returns the numeric feature with the given `name`, if any.
+    pub fn value(&self, name: &str) -> Option<f64> {
+        self.values.get(name).copied()
+    }
+}
@@ -1,7 +1,12 @@
 //! Rust bindings for [`irace`](https://github.com/MLopez-Ibanez/irace): Iterated Racing for Automatic Algorithm Configuration.
 
-use std::sync::{Arc, Once};
+use std::{
+    collections::HashSet,
+    path::Path,
+    sync::{Arc, Once},
+};
 
+use eyre::WrapErr;
 use mahf::params::Params;
 use pyo3::{
     exceptions::PyValueError,
@@ -10,18 +15,46 @@ use pyo3::{
 };
 
 use crate::{
-    experiment::FromPyDict, param_space::ParamSpace, runner::PyTargetRunner, scenario::Scenario,
+    console::{ConsoleCapture, ConsoleWriter},
+    experiment::FromPyDict,
+    observer::Observers,
+    param_space::ParamSpace,
+    runner::{
+        BatchTargetRunnerWrapper, ErasedTargetRunner, PyTargetRunner, RunnerStats,
+        TargetRunnerWrapper,
+    },
+    scenario::{ConsoleOutput, PanicPolicy, Penalty, Scenario, ScenarioHooks, TimeoutPolicy},
+    source::{ErasedInstances, InstanceLoader, InstanceSubset},
 };
 
+mod cache;
+mod command;
+mod console;
+mod evaluator;
 mod experiment;
 mod instance;
+mod log;
+mod observer;
 pub mod param_space;
+mod result;
 mod runner;
 pub mod scenario;
-
-pub use experiment::Experiment;
-pub use instance::{DistributedInstance, EvaluateDistributed};
-pub use runner::{Instance, TargetRunner};
+mod source;
+
+pub use cache::{CacheStats, CachedTargetRunner, ResultCache};
+pub use command::{CommandRunner, OutputFormat};
+pub use evaluator::{InstanceResults, RelativeDeviation, TargetEvaluator};
+pub use experiment::{CancellationToken, Experiment, ParamValue, ParamValues, RaceContext};
+pub use instance::{DistributedInstance, EvaluateDistributed, InstanceFeatures, NamedInstance};
+pub use log::{ExperimentLog, LogFormat};
+pub use observer::{Event, ExperimentInfo, Observer};
+pub use result::{ExperimentStatus, MetadataValue, TargetResult};
+#[cfg(feature = "async")]
+pub use runner::{AsyncBatchRunner, AsyncTargetRunner};
+pub use runner::{
+    BatchTargetRunner, ClassResults, Instance, ParallelTargetRunner, TargetRunner, TuningStats,
+};
+pub use source::{FileInstances, InstanceSource};
 
 static PYTHON_INIT: Once = Once::new();
 
@@ -49,29 +82,185 @@ fn register_site_packages() -> PyResult<()> {
 fn make_kwargs<'a, I: Instance>(
     py: Python<'a>,
     irace: &PyModule,
-    target_runner: impl TargetRunner<I>,
-    instances: impl IntoIterator<Item = I>,
-    scenario: Arc<Scenario>,
-    param_space: Arc<ParamSpace>,
-) -> PyResult<&'a PyDict> {
-    let instances: Vec<_> = instances.into_iter().collect();
-    let num_instances = instances.len();
+    run: Run<I>,
+    index: usize,
+) -> PyResult<(&'a PyDict, Py<PyTargetRunner>)> {
+    let Run {
+        target_runner,
+        target_evaluator,
+        instances,
+        instance_names,
+        instance_features,
+        stratified,
+        observers,
+        scenario,
+        param_space,
+    } = run;
+
+    let instance_classes: Vec<_> = instance_features
+        .into_iter()
+        .flatten()
+        .map(|features| features.class)
+        .collect();
+    let training_instances = if stratified {
+        scenario.stratified_training_instances(&instance_classes)
+    } else {
+        scenario.training_instances(instances.len())
+    };
 
     // Construct target runner.
     let target_runner = PyTargetRunner::new(
         target_runner,
-        instances,
+        instances.erase(),
+        instance_names.as_deref(),
         scenario.clone(),
         param_space.clone(),
-    );
+        target_evaluator,
+    )
+    .with_instance_classes(instance_classes)
+    .with_observers(observers)
+    .with_span(tracing::info_span!("run", index));
+    let is_batch = target_runner.is_batch();
+    let has_evaluator = target_runner.has_evaluator();
 
     // Transfer target runner to Python side.
+    let target_runner = Py::new(py, target_runner)?;
+    let mut hooks = ScenarioHooks::default();
+    if is_batch {
+        hooks.target_runner_parallel = Some(target_runner.getattr(py, "run_batch")?);
+    }
+    if has_evaluator {
+        hooks.target_evaluator = Some(target_runner.getattr(py, "evaluate")?);
+    }
+
     let kwargs = PyDict::new(py);
-    kwargs.set_item("target_runner", Py::new(py, target_runner)?)?;
-    kwargs.set_item("scenario", scenario.as_py_object(py, num_instances, irace)?)?;
+    kwargs.set_item("target_runner", target_runner.clone_ref(py))?;
+    kwargs.set_item(
+        "scenario",
+        scenario.as_py_object(
+            py,
+            training_instances,
+            instance_names.as_deref(),
+            irace,
+            hooks,
+        )?,
+    )?;
     kwargs.set_item("parameter_space", param_space.as_py_object(py, irace)?)?;
 
-    Ok(kwargs)
+    Ok((kwargs, target_runner))
+}
+
+/// Calls the hook of the target runner before `irace` is started.
+fn start_tuning(py: Python, target_runner: &Py<PyTargetRunner>) -> eyre::Result<()> {
+    let target_runner = target_runner.borrow(py);
+    let target_runner: &PyTargetRunner = &target_runner;
+    py.allow_threads(|| target_runner.start_tuning())
+}
+
+/// Calls the hooks of the target runner after `irace` returned, and reports its statistics.
+fn end_tuning(py: Python, target_runner: &Py<PyTargetRunner>) -> eyre::Result<()> {
+    let target_runner = target_runner.borrow(py);
+    let target_runner: &PyTargetRunner = &target_runner;
+    let result = py.allow_threads(|| target_runner.end_tuning());
+    report_stats(&target_runner.stats());
+    result
+}
+
+/// Combines the `error` with `further` errors which occurred while cleaning up after it.
+///
+/// The `error` is kept if there are no further errors, otherwise their messages are appended.
+fn combine_errors(error: eyre::Report, further: Vec<eyre::Report>) -> eyre::Report {
+    if further.is_empty() {
+        return error;
+    }
+    let further: Vec<_> = further.iter().map(|error| format!("{error:#}")).collect();
+    eyre::eyre!("{error:#}\nfurther errors:\n{}", further.join("\n"))
+}
+
+/// Returns the `result`, or the combined errors of the `result` and the `cleanup`
+/// performed after it.
+fn with_cleanup<T>(result: eyre::Result<T>, cleanup: Vec<eyre::Result<()>>) -> eyre::Result<T> {
+    let mut errors: Vec<_> = cleanup.into_iter().filter_map(Result::err).collect();
+    match result {
+        Err(error) => Err(combine_errors(error, errors)),
+        Ok(_) if !errors.is_empty() => {
+            let error = errors.remove(0);
+            Err(combine_errors(error, errors))
+        }
+        Ok(value) => Ok(value),
+    }
+}
+
+/// Evaluates the `code` calling `irace` with the `locals` between the start and end of the
+/// tuning of the `target_runners`, capturing the console output according to `console`.
+///
+/// The console output of the lifecycle hooks is captured as well.
+/// Started tunings are ended, and the console streams are restored, even if a tuning
+/// fails to start or `irace` fails.
+fn call_irace<'py>(
+    py: Python<'py>,
+    code: &str,
+    locals: &'py PyDict,
+    target_runners: &[Py<PyTargetRunner>],
+    console: &ConsoleOutput,
+) -> eyre::Result<&'py PyAny> {
+    let capture = ConsoleCapture::install(py, console)?;
+
+    let mut started = 0;
+    let mut result = Ok(());
+    for (index, target_runner) in target_runners.iter().enumerate() {
+        result = start_tuning(py, target_runner)
+            .wrap_err_with(|| format!("failed to start run {index}"));
+        if result.is_err() {
+            break;
+        }
+        started += 1;
+    }
+    let result = result.and_then(|()| Ok(Python::eval(py, code, None, Some(locals))?));
+
+    let tuning_ends = target_runners[..started]
+        .iter()
+        .enumerate()
+        .map(|(index, target_runner)| {
+            end_tuning(py, target_runner).wrap_err_with(|| format!("failed to end run {index}"))
+        })
+        .collect();
+    let result = with_cleanup(result, tuning_ends);
+
+    let restored = capture.map_or(Ok(()), |capture| capture.restore(py));
+    with_cleanup(result, vec![restored])
+}
+
+/// Logs the statistics collected by the target runner.
+fn report_stats(stats: &RunnerStats) {
+    let stats = stats.snapshot();
+    for (class, results) in &stats.class_results {
+        tracing::info!(
+            class = class.as_str(),
+            experiments = results.experiments,
+            mean_cost = results.mean_cost(),
+            failures = results.failures,
+            "results of instance class"
+        );
+    }
+
+    let num_failures = stats.num_failures();
+    if num_failures > 0 {
+        tracing::warn!(num_failures, "experiments failed");
+    }
+    if stats.abandoned_threads > 0 {
+        tracing::warn!(
+            abandoned_threads = stats.abandoned_threads,
+            "abandoned experiment threads are still running"
+        );
+    }
+    for (configuration_id, failures) in &stats.failures {
+        tracing::info!(
+            configuration_id = configuration_id.as_str(),
+            failures,
+            "failed experiments of configuration"
+        );
+    }
 }
 
 fn convert_result(result: &PyAny, param_space: &ParamSpace) -> PyResult<Vec<Params>> {
@@ -95,88 +284,501 @@ fn convert_result(result: &PyAny, param_space: &ParamSpace) -> PyResult<Vec<Para
 }
 
 /// [`irace`](https://github.com/MLopez-Ibanez/irace): Iterated Racing for Automatic Algorithm Configuration.
+///
+/// The `scenario` is [checked] for consistency before `irace` is started.
+/// See [`Run`] for further options, e.g. executing experiments in batches
+/// or loading instances on demand using [`Run::from_source`].
+///
+/// [checked]: Scenario::check
 pub fn irace<I: Instance>(
     target_runner: impl TargetRunner<I>,
     instances: impl IntoIterator<Item = I>,
     scenario: Arc<Scenario>,
     param_space: Arc<ParamSpace>,
 ) -> eyre::Result<Vec<Params>> {
-    init();
+    Run::new(target_runner, instances, scenario, param_space).irace()
+}
 
-    let params = Python::with_gil(|py| {
-        // Import the Python irace wrapper.
-        let irace = Python::import(py, "irace")?;
+/// Returns the names of all instances of the `source`.
+fn source_names<I>(source: &dyn InstanceSource<I>) -> Vec<String> {
+    (0..source.len()).map(|index| source.name(index)).collect()
+}
 
-        // Prepare the arguments to irace.
-        let locals = make_kwargs(
-            py,
-            irace,
-            target_runner,
-            instances,
-            scenario,
-            param_space.clone(),
-        )?;
-        locals.set_item("irace", irace)?;
+/// The instances of a [`Run`], which are either in memory or loaded on demand.
+enum RunInstances<I> {
+    InMemory(Vec<Arc<I>>),
+    Source {
+        source: Arc<dyn InstanceSource<I>>,
+        cache_capacity: usize,
+    },
+}
 
-        // Call irace.
-        let code = "irace.irace(target_runner=target_runner, scenario=scenario, parameter_space=parameter_space)";
-        let result = Python::eval(py, code, None, Some(locals))?;
+impl<I: Instance> RunInstances<I> {
+    fn in_memory(instances: impl IntoIterator<Item = I>) -> Self {
+        RunInstances::InMemory(instances.into_iter().map(Arc::new).collect())
+    }
 
-        // Extract the found params.
-        convert_result(result, &param_space)
-    })?;
+    fn from_source(source: impl InstanceSource<I>, cache_capacity: usize) -> Self {
+        RunInstances::Source {
+            source: Arc::new(source),
+            cache_capacity,
+        }
+    }
 
-    Ok(params)
+    fn len(&self) -> usize {
+        match self {
+            RunInstances::InMemory(instances) => instances.len(),
+            RunInstances::Source { source, .. } => source.len(),
+        }
+    }
+
+    /// Keeps only the instances at the given `indices`.
+    fn retain(self, indices: Vec<usize>) -> Self {
+        match self {
+            RunInstances::InMemory(instances) => RunInstances::InMemory(
+                indices
+                    .into_iter()
+                    .map(|index| instances[index].clone())
+                    .collect(),
+            ),
+            RunInstances::Source {
+                source,
+                cache_capacity,
+            } => RunInstances::Source {
+                source: Arc::new(InstanceSubset { source, indices }),
+                cache_capacity,
+            },
+        }
+    }
+
+    fn erase(self) -> Arc<dyn ErasedInstances> {
+        match self {
+            RunInstances::InMemory(instances) => Arc::new(instances) as Arc<dyn ErasedInstances>,
+            RunInstances::Source {
+                source,
+                cache_capacity,
+            } => Arc::new(InstanceLoader::new(source, cache_capacity)) as Arc<dyn ErasedInstances>,
+        }
+    }
 }
 
+/// A single `irace` run, i.e. a target runner together with its instances, scenario and parameter space.
 pub struct Run<I: Instance> {
-    target_runner: Box<dyn TargetRunner<I>>,
-    instances: Vec<I>,
+    target_runner: Arc<dyn ErasedTargetRunner>,
+    target_evaluator: Option<Arc<dyn TargetEvaluator>>,
+    instances: RunInstances<I>,
+    instance_names: Option<Vec<String>>,
+    instance_features: Option<Vec<InstanceFeatures>>,
+    stratified: bool,
+    observers: Observers,
     scenario: Arc<Scenario>,
     param_space: Arc<ParamSpace>,
 }
 
 impl<I: Instance> Run<I> {
+    fn from_parts(
+        target_runner: Arc<dyn ErasedTargetRunner>,
+        instances: RunInstances<I>,
+        scenario: Arc<Scenario>,
+        param_space: Arc<ParamSpace>,
+    ) -> Self {
+        Self {
+            target_runner,
+            target_evaluator: None,
+            instances,
+            instance_names: None,
+            instance_features: None,
+            stratified: false,
+            observers: Observers::default(),
+            scenario,
+            param_space,
+        }
+    }
+
+    /// Constructs a new `Run` executing one experiment at a time with the `target_runner`.
     pub fn new(
         target_runner: impl TargetRunner<I>,
         instances: impl IntoIterator<Item = I>,
         scenario: Arc<Scenario>,
         param_space: Arc<ParamSpace>,
     ) -> Self {
+        Self::from_parts(
+            Arc::new(TargetRunnerWrapper(Box::new(target_runner))),
+            RunInstances::in_memory(instances),
+            scenario,
+            param_space,
+        )
+    }
+
+    /// Constructs a new `Run` executing one experiment at a time with the `target_runner`,
+    /// loading the instances on demand from the `source`.
+    ///
+    /// See [`with_instance_source`](Self::with_instance_source) for the caching of instances.
+    pub fn from_source(
+        target_runner: impl TargetRunner<I>,
+        source: impl InstanceSource<I>,
+        cache_capacity: usize,
+        scenario: Arc<Scenario>,
+        param_space: Arc<ParamSpace>,
+    ) -> Self {
+        let instance_names = source_names(&source);
         Self {
-            target_runner: Box::new(target_runner),
-            instances: instances.into_iter().collect(),
+            instance_names: Some(instance_names),
+            ..Self::from_parts(
+                Arc::new(TargetRunnerWrapper(Box::new(target_runner))),
+                RunInstances::from_source(source, cache_capacity),
+                scenario,
+                param_space,
+            )
+        }
+    }
+
+    /// Constructs a new `Run` executing all experiments of a race step at once
+    /// with the `target_runner`.
+    ///
+    /// The experiments are not parallelized by `irace`, i.e. [`Scenario::num_jobs`] is left
+    /// to the batch target runner, e.g. a [`ParallelTargetRunner`].
+    pub fn batch(
+        target_runner: impl BatchTargetRunner<I>,
+        instances: impl IntoIterator<Item = I>,
+        scenario: Arc<Scenario>,
+        param_space: Arc<ParamSpace>,
+    ) -> Self {
+        Self::from_parts(
+            Arc::new(BatchTargetRunnerWrapper(Box::new(target_runner))),
+            RunInstances::in_memory(instances),
             scenario,
             param_space,
+        )
+    }
+
+    /// Constructs a new `Run` executing all experiments of a race step at once
+    /// with the `target_runner`, loading the instances on demand from the `source`.
+    ///
+    /// See [`batch`](Self::batch) and [`from_source`](Self::from_source).
+    pub fn batch_from_source(
+        target_runner: impl BatchTargetRunner<I>,
+        source: impl InstanceSource<I>,
+        cache_capacity: usize,
+        scenario: Arc<Scenario>,
+        param_space: Arc<ParamSpace>,
+    ) -> Self {
+        let instance_names = source_names(&source);
+        Self {
+            instance_names: Some(instance_names),
+            ..Self::from_parts(
+                Arc::new(BatchTargetRunnerWrapper(Box::new(target_runner))),
+                RunInstances::from_source(source, cache_capacity),
+                scenario,
+                param_space,
+            )
         }
     }
+
+    /// Computes the costs reported to `irace` using the `target_evaluator`,
+    /// after all configurations were executed on an instance.
+    ///
+    /// The evaluator needs the results of all experiments in this process, so it requires
+    /// either [`Scenario::num_jobs`] = 1 or a [batch](Self::batch) run,
+    /// as `irace` executes experiments in forked processes otherwise.
+    pub fn with_evaluator(mut self, target_evaluator: impl TargetEvaluator) -> Self {
+        self.target_evaluator = Some(Arc::new(target_evaluator));
+        self
+    }
+
+    /// Identifies the instances towards `irace` by the given `names` instead of their index.
+    ///
+    /// The names are passed as instance ids to the target runner, see [`Experiment::instance_name`].
+    /// They must be unique, and there must be a name for each instance.
+    pub fn with_instance_names(
+        mut self,
+        names: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.instance_names = Some(names.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Identifies the instances towards `irace` by their [name](NamedInstance::name).
+    ///
+    /// Instances loaded from an [`InstanceSource`] are named by the source.
+    pub fn with_named_instances(self) -> Self
+    where
+        I: NamedInstance,
+    {
+        let names: Vec<_> = match &self.instances {
+            RunInstances::InMemory(instances) => {
+                instances.iter().map(|instance| instance.name()).collect()
+            }
+            RunInstances::Source { source, .. } => source_names(source.as_ref()),
+        };
+        self.with_instance_names(names)
+    }
+
+    /// Loads the instances on demand from the `source`, instead of using the instances
+    /// given on construction.
+    ///
+    /// At most `cache_capacity` loaded instances (but at least one) are kept in memory,
+    /// besides the instances used by running experiments.
+    /// The instances are identified towards `irace` by their name in the source.
+    pub fn with_instance_source(
+        mut self,
+        source: impl InstanceSource<I>,
+        cache_capacity: usize,
+    ) -> Self {
+        self.instance_names = Some(source_names(&source));
+        self.instances = RunInstances::from_source(source, cache_capacity);
+        self
+    }
+
+    /// Attaches `features` to the instances, one for each instance.
+    ///
+    /// The features are used to [filter](Self::filter_instances) and [stratify](Self::stratified)
+    /// the instances, and the results are broken down by the class of the instances.
+    pub fn with_instance_features(
+        mut self,
+        features: impl IntoIterator<Item = InstanceFeatures>,
+    ) -> Self {
+        self.instance_features = Some(features.into_iter().collect());
+        self
+    }
+
+    /// Keeps only the instances whose features match the `predicate`, together with their
+    /// names and features.
+    ///
+    /// Instances without features are treated as having [no features](InstanceFeatures::new).
+    pub fn filter_instances(mut self, predicate: impl Fn(&InstanceFeatures) -> bool) -> Self {
+        let no_features = InstanceFeatures::new();
+        let features = self.instance_features.as_deref().unwrap_or_default();
+        let indices: Vec<_> = (0..self.instances.len())
+            .filter(|&index| predicate(features.get(index).unwrap_or(&no_features)))
+            .collect();
+
+        if let Some(names) = &mut self.instance_names {
+            *names = indices
+                .iter()
+                .filter_map(|&index| names.get(index).cloned())
+                .collect();
+        }
+        if let Some(features) = &mut self.instance_features {
+            *features = indices
+                .iter()
+                .filter_map(|&index| features.get(index).cloned())
+                .collect();
+        }
+        self.instances = self.instances.retain(indices);
+        self
+    }
+
+    /// Orders the training instances so each block covers all instance classes,
+    /// see [`InstanceFeatures::class`].
+    ///
+    /// This requires [instance features](Self::with_instance_features),
+    /// a [`Scenario::block_size`] that is a multiple of the number of classes,
+    /// and disabled [`Scenario::sample_instances`], as `irace` would shuffle the instances otherwise.
+    /// Blocks only cover all classes as long as instances of each class remain,
+    /// i.e. the classes should be of similar size.
+    pub fn stratified(mut self) -> Self {
+        self.stratified = true;
+        self
+    }
+
+    /// Notifies the `observer` of the progress of the tuning, see [`Event`].
+    ///
+    /// Multiple observers can be registered, which are notified in order.
+    ///
+    /// Observers require either [`Scenario::num_jobs`] = 1 or a [batch](Self::batch) run,
+    /// as `irace` executes experiments in forked processes otherwise, whose events would not
+    /// reach the observers. The same applies to the [`TuningStats`] and [`CacheStats`]
+    /// collected while executing experiments.
+    pub fn with_observer(mut self, observer: impl Observer) -> Self {
+        self.observers.0.push(Arc::new(observer));
+        self
+    }
+
+    /// Writes every executed experiment to a structured log file at `path`, see [`ExperimentLog`].
+    ///
+    /// The log is an observer, see [`with_observer`](Self::with_observer).
+    pub fn with_experiment_log(
+        self,
+        path: impl AsRef<Path>,
+        format: LogFormat,
+    ) -> eyre::Result<Self> {
+        let log = ExperimentLog::create(path, format, &self.param_space)?;
+        Ok(self.with_observer(log))
+    }
+
+    /// Returns if `irace` executes the experiments of this run in forked processes,
+    /// i.e. state recorded while executing them is lost.
+    ///
+    /// Batch runs are parallelized by the batch target runner instead.
+    fn forks_experiments(&self) -> bool {
+        self.scenario.num_jobs > 1 && !self.target_runner.is_batch()
+    }
+
+    /// Checks the run for consistency, see [`Scenario::check`].
+    fn check(&self) -> eyre::Result<()> {
+        self.scenario
+            .check(&self.param_space, self.instances.len())?;
+        eyre::ensure!(
+            !cfg!(windows) || !self.forks_experiments(),
+            "parallel execution (`num_jobs` = {}) is only supported for batch runs on Windows",
+            self.scenario.num_jobs
+        );
+
+        if let Some(names) = &self.instance_names {
+            eyre::ensure!(
+                names.len() == self.instances.len(),
+                "{} instance name(s) given for {} instance(s)",
+                names.len(),
+                self.instances.len()
+            );
+            let mut unique = HashSet::new();
+            for name in names {
+                eyre::ensure!(!name.is_empty(), "instance names must not be empty");
+                eyre::ensure!(unique.insert(name), "duplicate instance name {name:?}");
+            }
+        }
+
+        eyre::ensure!(
+            self.target_evaluator.is_none() || !self.forks_experiments(),
+            "a target evaluator requires `num_jobs` = 1 or a batch run, but `num_jobs` is {}",
+            self.scenario.num_jobs
+        );
+        eyre::ensure!(
+            self.observers.is_empty() || !self.forks_experiments(),
+            "observers require `num_jobs` = 1 or a batch run, but `num_jobs` is {}",
+            self.scenario.num_jobs
+        );
+        // The worst costs are only known to the process which recorded them.
+        let penalties = [
+            self.scenario.on_error.penalty,
+            match self.scenario.on_panic {
+                PanicPolicy::Penalty(penalty) => Some(penalty),
+                PanicPolicy::Abort => None,
+            },
+            match self.scenario.on_timeout {
+                Some(TimeoutPolicy::Penalty(penalty)) => Some(penalty),
+                _ => None,
+            },
+        ];
+        eyre::ensure!(
+            !self.forks_experiments()
+                || !penalties
+                    .iter()
+                    .flatten()
+                    .any(|penalty| matches!(penalty, Penalty::WorstOnInstance { .. })),
+            "`Penalty::WorstOnInstance` requires `num_jobs` = 1 or a batch run, but `num_jobs` is {}",
+            self.scenario.num_jobs
+        );
+
+        if let Some(features) = &self.instance_features {
+            eyre::ensure!(
+                features.len() == self.instances.len(),
+                "{} instance feature(s) given for {} instance(s)",
+                features.len(),
+                self.instances.len()
+            );
+        }
+
+        if self.stratified {
+            let features = self
+                .instance_features
+                .as_ref()
+                .ok_or_else(|| eyre::eyre!("stratified instances require instance features"))?;
+            eyre::ensure!(
+                !self.scenario.sample_instances,
+                "stratified instances require `sample_instances` to be disabled"
+            );
+            let num_classes = features
+                .iter()
+                .map(|features| &features.class)
+                .collect::<HashSet<_>>()
+                .len();
+            eyre::ensure!(
+                self.scenario.block_size % num_classes == 0,
+                "`block_size` ({}) must be a multiple of the number of instance classes ({num_classes})",
+                self.scenario.block_size
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Executes `irace` for this run.
+    ///
+    /// The run is [checked] for consistency before `irace` is started.
+    ///
+    /// [checked]: Scenario::check
+    pub fn irace(self) -> eyre::Result<Vec<Params>> {
+        let _span = tracing::info_span!("irace").entered();
+        self.check()?;
+
+        init();
+
+        let scenario = self.scenario.clone();
+        let param_space = self.param_space.clone();
+        let params = Python::with_gil(|py| -> eyre::Result<_> {
+            // Import the Python irace wrapper.
+            let irace = Python::import(py, "irace")?;
+
+            // Prepare the arguments to irace.
+            let (locals, target_runner) = make_kwargs(py, irace, self, 0)?;
+            locals.set_item("irace", irace)?;
+
+            let code = "irace.irace(target_runner=target_runner, scenario=scenario, parameter_space=parameter_space)";
+            let result = call_irace(py, code, locals, &[target_runner], &scenario.console)?;
+
+            // Extract the found params.
+            Ok(convert_result(result, &param_space)?)
+        })?;
+
+        Ok(params)
+    }
 }
 
+/// Executes multiple `irace` runs in parallel, using `num_jobs` jobs.
+///
+/// The scenario of each run is [checked] for consistency before any run is started.
+/// As the console output of all runs is captured together, it is written according to
+/// `console` instead of the [`Scenario::console`] of the runs.
+///
+/// [checked]: Scenario::check
 pub fn multi_irace<I: Instance>(
     runs: impl IntoIterator<Item = Run<I>>,
     num_jobs: usize,
     global_seed: Option<u32>,
+    console: ConsoleOutput,
 ) -> eyre::Result<Vec<Vec<Params>>> {
+    let _span = tracing::info_span!("multi_irace", num_jobs).entered();
+    eyre::ensure!(num_jobs > 0, "`num_jobs` must be at least 1");
+
+    let runs: Vec<_> = runs.into_iter().collect();
+    for (index, run) in runs.iter().enumerate() {
+        run.check()
+            .wrap_err_with(|| format!("invalid run {index}"))?;
+        if run.scenario.console != ConsoleOutput::Inherit && run.scenario.console != console {
+            tracing::warn!(
+                "the console output {:?} of run {index} is ignored by `multi_irace`, which writes it according to {console:?}",
+                run.scenario.console
+            );
+        }
+    }
+
     init();
 
-    let params = Python::with_gil(|py| {
+    let params = Python::with_gil(|py| -> eyre::Result<_> {
         // Import the Python irace wrapper.
         let irace = Python::import(py, "irace")?;
 
         // Convert all runs into their Python equivalent.
         let mut param_spaces = Vec::new();
+        let mut target_runners = Vec::new();
 
         let list = PyList::empty(py);
-        for run in runs {
-            let Run {
-                target_runner,
-                instances,
-                scenario,
-                param_space,
-            } = run;
-
-            param_spaces.push(param_space.clone());
-            let kwargs = make_kwargs(py, irace, target_runner, instances, scenario, param_space)?;
+        for (index, run) in runs.into_iter().enumerate() {
+            param_spaces.push(run.param_space.clone());
+            let (kwargs, target_runner) = make_kwargs(py, irace, run, index)?;
+            target_runners.push(target_runner);
             let py_run = irace.getattr("Run")?.call((), Some(kwargs))?;
             list.append(py_run)?;
         }
@@ -187,18 +789,18 @@ pub fn multi_irace<I: Instance>(
         locals.set_item("global_seed", global_seed)?;
         locals.set_item("irace", irace)?;
 
-        // Call multi-irace.
         let code = "irace.multi_irace(runs=runs, n_jobs=n_jobs, global_seed=global_seed)";
-        let results = Python::eval(py, code, None, Some(locals))?
+        let results = call_irace(py, code, locals, &target_runners, &console)?
             .downcast::<PyList>()
             .map_err(|_| PyValueError::new_err("`multi_irace` result should be a list"))?;
 
         // Convert results for each run.
-        results
+        let params = results
             .iter()
             .zip(param_spaces)
             .map(|(result, param_space)| convert_result(result, &param_space))
-            .collect::<Result<Vec<_>, _>>()
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(params)
     })?;
 
     Ok(params)
@@ -207,5 +809,123 @@ pub fn multi_irace<I: Instance>(
 #[pymodule]
 fn __irace(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyTargetRunner>()?;
+    m.add_class::<ConsoleWriter>()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scenario::FailurePolicy;
+
+    fn target_runner(_: &Scenario, _: Experiment<u32>) -> mahf::ExecResult<f64> {
+        Ok(0.0)
+    }
+
+    fn param_space() -> Arc<ParamSpace> {
+        let mut param_space = ParamSpace::new();
+        param_space.add_real("x", 0.0, 1.0, false);
+        Arc::new(param_space)
+    }
+
+    fn run(scenario: Scenario) -> Run<u32> {
+        Run::new(target_runner, 0..10, Arc::new(scenario), param_space())
+    }
+
+    #[test]
+    fn with_cleanup_combines_errors() {
+        assert_eq!(with_cleanup(Ok(1), vec![Ok(())]).unwrap(), 1);
+
+        let error = with_cleanup(Ok(1), vec![Ok(()), Err(eyre::eyre!("end failed"))]);
+        assert_eq!(error.unwrap_err().to_string(), "end failed");
+
+        let error = with_cleanup::<()>(
+            Err(eyre::eyre!("irace failed")),
+            vec![
+                Err(eyre::eyre!("end failed")),
+                Err(eyre::eyre!("restore failed")),
+            ],
+        );
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "irace failed\nfurther errors:\nend failed\nrestore failed"
+        );
+    }
+
+    #[test]
+    fn from_source_names_instances_by_the_source() {
+        struct Numbers;
+
+        impl InstanceSource<u32> for Numbers {
+            fn len(&self) -> usize {
+                3
+            }
+
+            fn name(&self, index: usize) -> String {
+                format!("number-{index}")
+            }
+
+            fn load(&self, index: usize) -> eyre::Result<u32> {
+                Ok(index as u32)
+            }
+        }
+
+        let scenario = Scenario::builder().max_experiments(1000).build();
+        let run = Run::from_source(target_runner, Numbers, 1, Arc::new(scenario), param_space());
+        assert_eq!(run.instances.len(), 3);
+        assert_eq!(
+            run.instance_names.as_deref(),
+            Some(&["number-0", "number-1", "number-2"].map(String::from)[..])
+        );
+        assert!(run.check().is_ok());
+    }
+
+    #[test]
+    fn check_rejects_observers_of_forked_experiments() {
+        let scenario = Scenario::builder()
+            .max_experiments(1000)
+            .num_jobs(4)
+            .build();
+        assert!(run(scenario.clone()).check().is_ok());
+        assert!(run(scenario).with_observer(|_: &Event| {}).check().is_err());
+
+        let batch = Run::batch(
+            ParallelTargetRunner::new(target_runner),
+            0..10,
+            Arc::new(
+                Scenario::builder()
+                    .max_experiments(1000)
+                    .num_jobs(4)
+                    .build(),
+            ),
+            param_space(),
+        );
+        assert!(batch.with_observer(|_: &Event| {}).check().is_ok());
+    }
+
+    #[test]
+    fn check_rejects_worst_penalties_in_forked_experiments() {
+        let worst = Penalty::WorstOnInstance {
+            factor: 2.0,
+            fallback: 100.0,
+        };
+        let scenario = Scenario::builder()
+            .max_experiments(1000)
+            .num_jobs(4)
+            .on_error(FailurePolicy::penalty(worst))
+            .build();
+        assert!(run(scenario.clone()).check().is_err());
+
+        let sequential = Scenario {
+            num_jobs: 1,
+            ..scenario.clone()
+        };
+        assert!(run(sequential).check().is_ok());
+
+        let fixed = Scenario {
+            on_error: FailurePolicy::penalty(Penalty::Fixed(100.0)),
+            ..scenario
+        };
+        assert!(run(fixed).check().is_ok());
+    }
+}
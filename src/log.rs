@@ -0,0 +1,369 @@
+//! Logging executed experiments to a structured file.
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufWriter, Write as _},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::Duration,
+};
+
+use eyre::{bail, WrapErr};
+
+use crate::{
+    experiment::ParamValue,
+    observer::{Event, ExperimentInfo, Observer},
+    param_space::{ParamSpace, ParamSubspace},
+    result::{MetadataValue, TargetResult},
+};
+
+/// The file format of an [`ExperimentLog`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Comma-separated values with a header line.
+    #[default]
+    Csv,
+    /// One JSON object per line.
+    JsonLines,
+}
+
+/// A single field of a log entry.
+enum Field {
+    Null,
+    Bool(bool),
+    Integer(i64),
+    Unsigned(u64),
+    Real(f64),
+    Text(String),
+}
+
+impl Field {
+    fn write_csv(&self, line: &mut String) {
+        match self {
+            Field::Null => {}
+            Field::Bool(value) => write!(line, "{value}").unwrap(),
+            Field::Integer(value) => write!(line, "{value}").unwrap(),
+            Field::Unsigned(value) => write!(line, "{value}").unwrap(),
+            Field::Real(value) => write!(line, "{value}").unwrap(),
+            Field::Text(value) => line.push_str(&csv_escape(value)),
+        }
+    }
+
+    fn write_json(&self, line: &mut String) {
+        match self {
+            Field::Null => line.push_str("null"),
+            Field::Bool(value) => write!(line, "{value}").unwrap(),
+            Field::Integer(value) => write!(line, "{value}").unwrap(),
+            Field::Unsigned(value) => write!(line, "{value}").unwrap(),
+            // JSON has no representation of infinite and NaN values.
+            Field::Real(value) if !value.is_finite() => line.push_str("null"),
+            Field::Real(value) => write!(line, "{value}").unwrap(),
+            Field::Text(value) => line.push_str(&json_escape(value)),
+        }
+    }
+}
+
+impl From<&MetadataValue> for Field {
+    fn from(value: &MetadataValue) -> Self {
+        match value {
+            MetadataValue::Bool(value) => Field::Bool(*value),
+            MetadataValue::Integer(value) => Field::Integer(*value),
+            MetadataValue::Real(value) => Field::Real(*value),
+            MetadataValue::Text(value) => Field::Text(value.clone()),
+        }
+    }
+}
+
+/// Quotes the `value` if it contains a separator, quote or line break.
+fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Renders the `value` as JSON string.
+fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// The columns before the parameter values.
+const LEADING_COLUMNS: [&str; 4] = ["configuration_id", "iteration", "instance", "seed"];
+
+/// The columns after the parameter values.
+const TRAILING_COLUMNS: [&str; 4] = ["cost", "time", "status", "error"];
+
+/// A column of the parameter values.
+struct ParamColumn {
+    name: String,
+    /// The labels of the variants, if the parameter is categorical.
+    labels: Option<Vec<String>>,
+}
+
+/// An [`Observer`] writing every executed experiment to a structured file, see [`LogFormat`].
+///
+/// Each entry contains the configuration id, iteration, instance id, seed, the values of all
+/// parameters, cost, time (in seconds), status and error of an experiment.
+/// Categorical values are written as the [label](crate::param_space::DiscreteSubspace::labels)
+/// of their variant, conditional parameters which are not active are left empty.
+/// Parameters must not be named like the other columns.
+/// The status is the [`ExperimentStatus`](crate::ExperimentStatus) of the result,
+/// or `error` if the experiment aborted the tuning.
+/// The cost is the raw cost of the experiment, not the cost computed by a
+/// [`TargetEvaluator`](crate::TargetEvaluator), see [`Event::ExperimentFinished`].
+///
+/// Each entry is flushed immediately, so partial logs survive crashes.
+/// Register the log using [`Run::with_observer`](crate::Run::with_observer),
+/// or [`Run::with_experiment_log`](crate::Run::with_experiment_log).
+pub struct ExperimentLog {
+    path: PathBuf,
+    format: LogFormat,
+    params: Vec<ParamColumn>,
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl ExperimentLog {
+    /// Creates the log at `path` for experiments of the `param_space`, overwriting an existing file.
+    pub fn create(
+        path: impl AsRef<Path>,
+        format: LogFormat,
+        param_space: &ParamSpace,
+    ) -> eyre::Result<Self> {
+        let mut flat_space = param_space.clone();
+        flat_space.flatten();
+        if let Some(name) = flat_space
+            .iter()
+            .map(|(name, _)| name)
+            .find(|name| LEADING_COLUMNS.contains(name) || TRAILING_COLUMNS.contains(name))
+        {
+            bail!("parameter `{name}` clashes with a column of the experiment log");
+        }
+        let params = flat_space
+            .iter()
+            .map(|(name, subspace)| ParamColumn {
+                name: name.to_owned(),
+                labels: match subspace {
+                    ParamSubspace::Categorical(categorical) => Some(categorical.labels.clone()),
+                    _ => None,
+                },
+            })
+            .collect();
+
+        let path = path.as_ref().to_owned();
+        let file = File::create(&path)
+            .wrap_err_with(|| format!("failed to create experiment log {}", path.display()))?;
+        let log = Self {
+            path,
+            format,
+            params,
+            writer: Mutex::new(BufWriter::new(file)),
+        };
+        if format == LogFormat::Csv {
+            let header: Vec<_> = log.columns().map(csv_escape).collect();
+            log.write_line(&header.join(","))?;
+        }
+        Ok(log)
+    }
+
+    /// Returns the path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the names of all columns.
+    fn columns(&self) -> impl Iterator<Item = &str> {
+        let params = self.params.iter().map(|param| param.name.as_str());
+        LEADING_COLUMNS
+            .into_iter()
+            .chain(params)
+            .chain(TRAILING_COLUMNS)
+    }
+
+    fn write_line(&self, line: &str) -> eyre::Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        writeln!(writer, "{line}")
+            .and_then(|_| writer.flush())
+            .wrap_err_with(|| format!("failed to write experiment log {}", self.path.display()))
+    }
+
+    /// Returns the fields of an entry, in the order of the columns.
+    fn fields(
+        &self,
+        experiment: &ExperimentInfo,
+        result: &Result<TargetResult, String>,
+        duration: Option<Duration>,
+    ) -> Vec<Field> {
+        let mut fields = vec![
+            Field::Text(experiment.configuration_id.clone()),
+            experiment
+                .context
+                .iteration
+                .map_or(Field::Null, |iteration| Field::Integer(iteration.into())),
+            experiment
+                .instance_id
+                .clone()
+                .map_or(Field::Null, Field::Text),
+            Field::Unsigned(experiment.seed),
+        ];
+
+        for param in &self.params {
+            fields.push(match experiment.values.get(&param.name) {
+                None => Field::Null,
+                Some(ParamValue::Real(value)) => Field::Real(*value),
+                Some(ParamValue::Integer(value)) => Field::Integer((*value).into()),
+                Some(ParamValue::Bool(value)) => Field::Bool(*value),
+                Some(ParamValue::Categorical(index)) => {
+                    match param.labels.as_ref().and_then(|labels| labels.get(*index)) {
+                        Some(label) => Field::Text(label.clone()),
+                        None => Field::Integer(*index as i64),
+                    }
+                }
+            });
+        }
+
+        match result {
+            Ok(result) => {
+                fields.push(Field::Real(result.cost));
+                fields.push(
+                    result
+                        .time
+                        .or(duration)
+                        .map_or(Field::Null, |time| Field::Real(time.as_secs_f64())),
+                );
+                fields.push(Field::Text(result.status.to_string()));
+                fields.push(
+                    result
+                        .metadata
+                        .get("error")
+                        .map_or(Field::Null, Field::from),
+                );
+            }
+            Err(error) => {
+                fields.push(Field::Null);
+                fields.push(duration.map_or(Field::Null, |time| Field::Real(time.as_secs_f64())));
+                fields.push(Field::Text("error".to_owned()));
+                fields.push(Field::Text(error.clone()));
+            }
+        }
+
+        fields
+    }
+
+    /// Writes the entry of an executed experiment.
+    fn record(
+        &self,
+        experiment: &ExperimentInfo,
+        result: &Result<TargetResult, String>,
+        duration: Option<Duration>,
+    ) -> eyre::Result<()> {
+        let fields = self.fields(experiment, result, duration);
+
+        let mut line = String::new();
+        match self.format {
+            LogFormat::Csv => {
+                for (index, field) in fields.iter().enumerate() {
+                    if index > 0 {
+                        line.push(',');
+                    }
+                    field.write_csv(&mut line);
+                }
+            }
+            LogFormat::JsonLines => {
+                line.push('{');
+                for (index, (column, field)) in self.columns().zip(&fields).enumerate() {
+                    if index > 0 {
+                        line.push(',');
+                    }
+                    write!(line, "{}:", json_escape(column)).unwrap();
+                    field.write_json(&mut line);
+                }
+                line.push('}');
+            }
+        }
+
+        self.write_line(&line)
+    }
+}
+
+impl Observer for ExperimentLog {
+    fn notify(&self, event: &Event) {
+        if let Event::ExperimentFinished {
+            experiment,
+            result,
+            duration,
+        } = event
+        {
+            // Observers can't fail the tuning, so errors are only reported.
+            if let Err(e) = self.record(experiment, result, *duration) {
+                tracing::warn!("{e:#}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_rejects_parameters_named_like_columns() {
+        let mut param_space = ParamSpace::new();
+        param_space.add_real("cost", 0.0, 1.0, false);
+        let error = ExperimentLog::create("experiments.csv", LogFormat::Csv, &param_space)
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "parameter `cost` clashes with a column of the experiment log"
+        );
+    }
+
+    #[test]
+    fn csv_escape_quotes_only_when_needed() {
+        assert_eq!(csv_escape("plain"), "plain");
+        assert_eq!(csv_escape(""), "");
+        assert_eq!(csv_escape("a,b"), "\"a,b\"");
+        assert_eq!(csv_escape("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_escape("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_escape("cr\r"), "\"cr\r\"");
+    }
+
+    #[test]
+    fn json_escape_special_characters() {
+        assert_eq!(json_escape("plain"), "\"plain\"");
+        assert_eq!(json_escape("a\"b\\c"), "\"a\\\"b\\\\c\"");
+        assert_eq!(json_escape("\n\r\t"), "\"\\n\\r\\t\"");
+        assert_eq!(json_escape("\u{1}\u{7f}"), "\"\\u0001\\u007f\"");
+        assert_eq!(json_escape("ünïcode ✓"), "\"ünïcode ✓\"");
+    }
+
+    #[test]
+    fn json_fields_without_representation_are_null() {
+        let mut line = String::new();
+        for field in [
+            Field::Real(f64::NAN),
+            Field::Real(f64::INFINITY),
+            Field::Null,
+        ] {
+            field.write_json(&mut line);
+            line.push(' ');
+        }
+        assert_eq!(line, "null null null ");
+    }
+}
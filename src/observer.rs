@@ -0,0 +1,134 @@
+//! Observing the progress of a tuning.
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    experiment::{ExperimentData, ParamValues, RaceContext},
+    result::TargetResult,
+    runner::TuningStats,
+};
+
+/// Identifies an experiment in an [`Event`].
+#[derive(Debug, Clone)]
+pub struct ExperimentInfo {
+    pub configuration_id: String,
+    pub instance_id: Option<String>,
+    pub seed: u64,
+    /// The raw parameter values of the configuration.
+    pub values: ParamValues,
+    /// The state of the race the experiment is executed in.
+    pub context: RaceContext,
+}
+
+impl ExperimentInfo {
+    pub(crate) fn new(data: &ExperimentData) -> Self {
+        Self {
+            configuration_id: data.id.clone(),
+            instance_id: data.instance_id.clone(),
+            seed: data.seed,
+            values: data.values.clone(),
+            context: data.context.clone(),
+        }
+    }
+}
+
+/// An event of a running tuning, see [`Observer`].
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// An experiment is about to be executed.
+    ExperimentStarted(ExperimentInfo),
+    /// An experiment was executed, including retries.
+    ExperimentFinished {
+        experiment: ExperimentInfo,
+        /// The result reported to `irace`, or the error which aborts the tuning.
+        ///
+        /// Failed experiments are reported with the penalty cost and their
+        /// [`status`](TargetResult::status), according to the policies of the scenario.
+        ///
+        /// This is the raw cost returned by the target runner: if the run has a
+        /// [`TargetEvaluator`](crate::TargetEvaluator), `irace` receives the cost computed
+        /// by the evaluator instead.
+        result: Result<TargetResult, String>,
+        /// The wall-clock duration of the experiment.
+        ///
+        /// This is `None` for experiments executed in a batch, unless the batch target runner
+        /// reported their time.
+        duration: Option<Duration>,
+    },
+    /// A configuration was eliminated from the race of an iteration.
+    ///
+    /// Eliminations are reported at the end of the iteration, and only if the elites are known,
+    /// see [`IterationFinished`](Self::IterationFinished).
+    ConfigurationEliminated {
+        configuration_id: String,
+        iteration: u32,
+    },
+    /// A configuration became an elite, i.e. survived the race of an iteration without being
+    /// an elite of the previous iteration.
+    ///
+    /// New elites are only reported if the elites are known,
+    /// see [`IterationFinished`](Self::IterationFinished).
+    NewElite {
+        configuration_id: String,
+        iteration: u32,
+    },
+    /// The race of an iteration finished.
+    IterationFinished {
+        iteration: u32,
+        /// The ids of the elite configurations, best first, or `None` if `irace` does not pass
+        /// the [`RaceContext::num_evaluated`] of experiments.
+        ///
+        /// The elites are an approximation: the configurations evaluated on the most instances,
+        /// ordered by their mean cost in the iteration. `irace` itself ranks the surviving
+        /// configurations by the statistical test of the scenario, which may differ.
+        elites: Option<Vec<String>>,
+    },
+    /// The tuning finished, also if it failed.
+    TuningFinished(TuningStats),
+}
+
+/// Trait representing an observer of a tuning, which receives structured [`Event`]s,
+/// e.g. to implement logging, dashboards or alerts.
+///
+/// Observers are registered using [`Run::with_observer`](crate::Run::with_observer).
+/// They are notified from the threads executing experiments, i.e. possibly concurrently,
+/// and should return quickly to not delay the tuning.
+///
+/// Iterations are only tracked if `irace` passes the [`RaceContext::iteration`] of experiments,
+/// otherwise only experiment events are reported.
+pub trait Observer: Send + Sync + 'static {
+    fn notify(&self, event: &Event);
+}
+
+impl Observer for Box<dyn Observer> {
+    fn notify(&self, event: &Event) {
+        (**self).notify(event)
+    }
+}
+
+impl<F> Observer for F
+where
+    F: Fn(&Event) + Send + Sync + 'static,
+{
+    fn notify(&self, event: &Event) {
+        (self)(event)
+    }
+}
+
+/// The observers of a run.
+#[derive(Clone, Default)]
+pub(crate) struct Observers(pub Vec<Arc<dyn Observer>>);
+
+impl Observers {
+    /// Returns if there are no observers, i.e. events don't need to be constructed.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Notifies all observers of the `event`.
+    pub fn notify(&self, event: Event) {
+        for observer in &self.0 {
+            observer.notify(&event);
+        }
+    }
+}
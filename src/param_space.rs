@@ -1,15 +1,22 @@
 //! Specifying parameter spaces.
 
-use std::fmt::{Debug, Formatter};
+use std::{
+    any::Any,
+    fmt::{Debug, Formatter},
+};
 
+use eyre::ensure;
 use indexmap::IndexMap;
-use mahf::params::{Param, Parameter};
+use mahf::params::{Param, Parameter, Params};
 use num::Num;
 use pyo3::{
     exceptions::PyValueError,
     types::{PyDict, PyList, PyModule},
     PyObject, PyResult, Python, ToPyObject,
 };
+use rand::Rng;
+
+use crate::experiment::{to_params, ParamValue, ParamValues};
 
 /// A numerical parameter space with lower and upper bounds.
 #[derive(Clone)]
@@ -48,18 +55,39 @@ impl<T: Debug> Debug for NumericalSubspace<T> {
 pub struct DiscreteSubspace<T> {
     pub name: String,
     pub variants: Vec<T>,
+    /// The human-readable label of each variant, e.g. used by the [`ExperimentLog`].
+    ///
+    /// [`ExperimentLog`]: crate::ExperimentLog
+    pub labels: Vec<String>,
 }
 
-impl<T> DiscreteSubspace<T> {
+impl<T: Debug + 'static> DiscreteSubspace<T> {
     /// Constructs a new `DiscreteSubspace`.
+    ///
+    /// String variants are labeled by themselves, other variants by their `Debug` representation.
     pub fn new(name: impl Into<String>, values: impl IntoIterator<Item = T>) -> Self {
+        let variants: Vec<_> = values.into_iter().collect();
         Self {
             name: name.into(),
-            variants: values.into_iter().collect(),
+            labels: variants.iter().map(variant_label).collect(),
+            variants,
         }
     }
 }
 
+/// Returns the label of a categorical `variant`: strings are labeled by themselves,
+/// other types by their `Debug` representation.
+fn variant_label<T: Debug + 'static>(variant: &T) -> String {
+    let any: &dyn Any = variant;
+    if let Some(label) = any.downcast_ref::<String>() {
+        label.clone()
+    } else if let Some(label) = any.downcast_ref::<&str>() {
+        (*label).to_owned()
+    } else {
+        format!("{variant:?}")
+    }
+}
+
 impl<T: Debug> Debug for DiscreteSubspace<T> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -170,16 +198,27 @@ impl ParamSpace {
     }
 
     /// Adds a new categorical parameter with the given `name` and `variants` of type `T`.
-    pub fn add_categorical<T: Parameter>(
+    ///
+    /// String variants are labeled by themselves, other variants by their `Debug` representation,
+    /// see [`DiscreteSubspace::labels`].
+    pub fn add_categorical<T: Parameter + Debug + 'static>(
         &mut self,
         name: impl Into<String>,
         variants: impl IntoIterator<Item = T>,
     ) -> &mut Self {
         let name = name.into();
-        let discrete = DiscreteSubspace::new(
-            name.clone(),
-            variants.into_iter().map(|value| Param::new(value)),
-        );
+        let (variants, labels) = variants
+            .into_iter()
+            .map(|value| {
+                let label = variant_label(&value);
+                (Param::new(value), label)
+            })
+            .unzip();
+        let discrete = DiscreteSubspace {
+            name: name.clone(),
+            variants,
+            labels,
+        };
         self.add_raw(name, ParamSubspace::Categorical(discrete))
     }
 
@@ -235,7 +274,7 @@ impl ParamSpace {
     }
 
     /// Adds a new categorical parameter with the given `name` and `variants` of type `T`.
-    pub fn with_categorical<T: Parameter>(
+    pub fn with_categorical<T: Parameter + Debug + 'static>(
         mut self,
         name: impl Into<String>,
         variants: impl IntoIterator<Item = T>,
@@ -271,6 +310,137 @@ impl ParamSpace {
         self.subspaces.get(name)
     }
 
+    /// Returns the names and subspaces of all parameters, in the order they were added.
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&str, &ParamSubspace)> {
+        self.subspaces
+            .iter()
+            .map(|(name, subspace)| (name.as_str(), subspace))
+    }
+
+    /// Returns the number of parameters, counting the parameters of nested spaces individually.
+    pub fn num_params(&self) -> usize {
+        self.subspaces
+            .values()
+            .map(|subspace| match subspace {
+                ParamSubspace::Nested(nested) => nested.num_params(),
+                _ => 1,
+            })
+            .sum()
+    }
+
+    /// Returns if the parameter space contains any nested parameter spaces.
+    pub fn is_nested(&self) -> bool {
+        self.subspaces.values().any(ParamSubspace::is_nested)
+    }
+
+    /// Checks that a configuration can be sampled from the parameter space.
+    ///
+    /// Fails if the bounds of a numerical parameter are not finite or reversed, a real parameter
+    /// with `log` enabled has a non-positive lower bound, or a categorical parameter has no variants.
+    pub fn check(&self) -> eyre::Result<()> {
+        self.check_with_prefix("")
+    }
+
+    fn check_with_prefix(&self, prefix: &str) -> eyre::Result<()> {
+        for (name, subspace) in &self.subspaces {
+            let key = format!("{prefix}{name}");
+            match subspace {
+                ParamSubspace::Real(real) => {
+                    ensure!(
+                        real.lower.is_finite() && real.upper.is_finite(),
+                        "parameter `{key}` has non-finite bounds"
+                    );
+                    ensure!(
+                        real.lower <= real.upper,
+                        "parameter `{key}` has a lower bound greater than its upper bound"
+                    );
+                    ensure!(
+                        !real.log || real.lower > 0.0,
+                        "parameter `{key}` is sampled in logarithmic space, \
+                         but its lower bound is not positive"
+                    );
+                }
+                ParamSubspace::Integer(integer) => ensure!(
+                    integer.lower <= integer.upper,
+                    "parameter `{key}` has a lower bound greater than its upper bound"
+                ),
+                ParamSubspace::Bool(_) => {}
+                ParamSubspace::Categorical(categorical) => ensure!(
+                    !categorical.variants.is_empty(),
+                    "parameter `{key}` has no variants"
+                ),
+                ParamSubspace::Nested(nested) => nested.check_with_prefix(&format!("{key}."))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Samples a random configuration uniformly from the parameter space.
+    ///
+    /// Parameters with `log` enabled are sampled uniformly in logarithmic space.
+    /// Parameters of nested spaces are inserted using their flat key (see [`flatten`]).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameter space fails the [`check`](Self::check).
+    ///
+    /// [`flatten`]: Self::flatten
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Params {
+        let values = self.sample_values(rng);
+        let mut flat = self.clone();
+        flat.flatten();
+        to_params(&values, &flat)
+    }
+
+    /// Samples the raw values of a random configuration uniformly from the parameter space.
+    ///
+    /// See [`sample`](Self::sample) for details.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameter space fails the [`check`](Self::check).
+    pub fn sample_values<R: Rng + ?Sized>(&self, rng: &mut R) -> ParamValues {
+        let mut values = ParamValues::new();
+        self.sample_into(rng, "", &mut values);
+        values
+    }
+
+    fn sample_into<R: Rng + ?Sized>(&self, rng: &mut R, prefix: &str, values: &mut ParamValues) {
+        for (name, subspace) in &self.subspaces {
+            let key = format!("{prefix}{name}");
+            let value = match subspace {
+                ParamSubspace::Real(real) => {
+                    let value = if real.log {
+                        rng.gen_range(real.lower.ln()..=real.upper.ln()).exp()
+                    } else {
+                        rng.gen_range(real.lower..=real.upper)
+                    };
+                    ParamValue::Real(value.clamp(real.lower, real.upper))
+                }
+                ParamSubspace::Integer(integer) => {
+                    let value = if integer.log {
+                        let lower = (integer.lower.max(1) as f64).ln();
+                        let upper = (integer.upper.max(1) as f64).ln();
+                        (rng.gen_range(lower..=upper).exp().round() as u32)
+                            .clamp(integer.lower, integer.upper)
+                    } else {
+                        rng.gen_range(integer.lower..=integer.upper)
+                    };
+                    ParamValue::Integer(value)
+                }
+                ParamSubspace::Bool(_) => ParamValue::Bool(rng.gen_bool(0.5)),
+                ParamSubspace::Categorical(categorical) => {
+                    ParamValue::Categorical(rng.gen_range(0..categorical.variants.len()))
+                }
+                ParamSubspace::Nested(nested) => {
+                    nested.sample_into(rng, &format!("{key}."), values);
+                    continue;
+                }
+            };
+            values.insert(key, value);
+        }
+    }
+
     /// Flattens the parameter space recursively.
     ///
     /// Nested parameter spaces are inserted into the top-level space by concatenating the key
@@ -381,3 +551,64 @@ impl ParamSpace {
         Ok(parameter_space.to_object(py))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categorical_variants_are_labeled_by_their_value() {
+        let mut space = ParamSpace::new();
+        space
+            .add_bool("flag")
+            .add_categorical_names("mode", ["fast", "say \"hi\""]);
+        let Some(ParamSubspace::Bool(flag)) = space.get_raw("flag") else {
+            panic!("expected a bool parameter");
+        };
+        assert_eq!(flag.labels, ["true", "false"]);
+        let Some(ParamSubspace::Categorical(mode)) = space.get_raw("mode") else {
+            panic!("expected a categorical parameter");
+        };
+        assert_eq!(mode.labels, ["fast", "say \"hi\""]);
+    }
+
+    #[test]
+    fn check_accepts_valid_spaces() {
+        let mut nested = ParamSpace::new();
+        nested.add_integer("size", 1, 10, true);
+        let mut space = ParamSpace::new();
+        space
+            .add_real("rate", 1e-3, 1.0, true)
+            .add_real("offset", -1.0, 1.0, false)
+            .add_bool("flag")
+            .add_categorical_names("mode", ["a", "b"])
+            .add_nested("inner", nested);
+        assert!(space.check().is_ok());
+    }
+
+    #[test]
+    fn check_rejects_unsampleable_spaces() {
+        let mut space = ParamSpace::new();
+        space.add_real("rate", 0.0, 1.0, true);
+        assert!(space.check().is_err());
+
+        let mut space = ParamSpace::new();
+        space.add_real("rate", 2.0, 1.0, false);
+        assert!(space.check().is_err());
+
+        let mut space = ParamSpace::new();
+        space.add_real("rate", 0.0, f64::INFINITY, false);
+        assert!(space.check().is_err());
+
+        let mut space = ParamSpace::new();
+        space.add_integer("size", 5, 1, false);
+        assert!(space.check().is_err());
+
+        let mut nested = ParamSpace::new();
+        nested.add_categorical_names("mode", Vec::<String>::new());
+        let mut space = ParamSpace::new();
+        space.add_nested("inner", nested);
+        let error = space.check().unwrap_err();
+        assert!(error.to_string().contains("`inner.mode`"));
+    }
+}
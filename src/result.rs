@@ -0,0 +1,184 @@
+use std::{
+    fmt::{Display, Formatter},
+    time::Duration,
+};
+
+use indexmap::IndexMap;
+use mahf::SingleObjective;
+use pyo3::{types::PyDict, PyObject, PyResult, Python, ToPyObject};
+
+/// A value of the user metadata attached to a [`TargetResult`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetadataValue {
+    Bool(bool),
+    Integer(i64),
+    Real(f64),
+    Text(String),
+}
+
+impl From<bool> for MetadataValue {
+    fn from(value: bool) -> Self {
+        MetadataValue::Bool(value)
+    }
+}
+
+impl From<i64> for MetadataValue {
+    fn from(value: i64) -> Self {
+        MetadataValue::Integer(value)
+    }
+}
+
+impl From<u32> for MetadataValue {
+    fn from(value: u32) -> Self {
+        MetadataValue::Integer(value.into())
+    }
+}
+
+impl From<f64> for MetadataValue {
+    fn from(value: f64) -> Self {
+        MetadataValue::Real(value)
+    }
+}
+
+impl From<String> for MetadataValue {
+    fn from(value: String) -> Self {
+        MetadataValue::Text(value)
+    }
+}
+
+impl From<&str> for MetadataValue {
+    fn from(value: &str) -> Self {
+        MetadataValue::Text(value.to_owned())
+    }
+}
+
+impl ToPyObject for MetadataValue {
+    fn to_object(&self, py: Python<'_>) -> PyObject {
+        match self {
+            MetadataValue::Bool(value) => value.to_object(py),
+            MetadataValue::Integer(value) => value.to_object(py),
+            MetadataValue::Real(value) => value.to_object(py),
+            MetadataValue::Text(value) => value.to_object(py),
+        }
+    }
+}
+
+/// The status of an experiment, see [`TargetResult::status`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ExperimentStatus {
+    /// The target runner returned a result.
+    #[default]
+    Ok,
+    /// The target runner returned an error, and the experiment was reported with a penalty cost.
+    Failed,
+    /// The target runner panicked, and the experiment was reported with a penalty cost.
+    Panicked,
+    /// The experiment exceeded its time limit, and was reported according to the timeout policy.
+    Timeout,
+}
+
+impl ExperimentStatus {
+    /// Returns the name of the status, as reported to `irace` and written to logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExperimentStatus::Ok => "ok",
+            ExperimentStatus::Failed => "failed",
+            ExperimentStatus::Panicked => "panicked",
+            ExperimentStatus::Timeout => "timeout",
+        }
+    }
+
+    /// Returns if the target runner returned a result.
+    pub fn is_ok(&self) -> bool {
+        *self == ExperimentStatus::Ok
+    }
+}
+
+impl Display for ExperimentStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The result of a single execution of the [`TargetRunner`].
+///
+/// Besides the `cost` to minimize, the result can carry the elapsed `time`
+/// and arbitrary user `metadata`.
+/// If the time is not measured by the target runner, the wall-clock time of the execution is used.
+///
+/// [`TargetRunner`]: crate::TargetRunner
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetResult {
+    /// The cost of the execution, which is minimized by `irace`.
+    pub cost: f64,
+    /// The elapsed time of the execution.
+    pub time: Option<Duration>,
+    /// User metadata.
+    pub metadata: IndexMap<String, MetadataValue>,
+    /// The status of the experiment.
+    ///
+    /// Target runners return results with [`ExperimentStatus::Ok`], the other statuses are set
+    /// for failed experiments according to the policies of the [`Scenario`].
+    ///
+    /// [`Scenario`]: crate::scenario::Scenario
+    pub status: ExperimentStatus,
+}
+
+impl TargetResult {
+    /// Constructs a new `TargetResult` with the given `cost`.
+    pub fn new(cost: f64) -> Self {
+        Self {
+            cost,
+            time: None,
+            metadata: IndexMap::new(),
+            status: ExperimentStatus::Ok,
+        }
+    }
+
+    /// Sets the elapsed `time` of the execution.
+    pub fn with_time(mut self, time: Duration) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Adds the metadata `value` under the given `key`.
+    pub fn with_metadata(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<MetadataValue>,
+    ) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    pub(crate) fn as_py_object(&self, py: Python) -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        dict.set_item("cost", self.cost)?;
+        dict.set_item("time", self.time.map(|time| time.as_secs_f64()))?;
+
+        if !self.metadata.is_empty() || !self.status.is_ok() {
+            let metadata = PyDict::new(py);
+            for (key, value) in &self.metadata {
+                metadata.set_item(key, value)?;
+            }
+            if !self.status.is_ok() {
+                metadata.set_item("status", self.status.as_str())?;
+            }
+            dict.set_item("metadata", metadata)?;
+        }
+
+        Ok(dict.to_object(py))
+    }
+}
+
+impl From<f64> for TargetResult {
+    fn from(cost: f64) -> Self {
+        Self::new(cost)
+    }
+}
+
+impl From<SingleObjective> for TargetResult {
+    fn from(objective: SingleObjective) -> Self {
+        Self::new(objective.value())
+    }
+}
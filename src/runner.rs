@@ -1,113 +1,1079 @@
-use std::sync::Arc;
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    fmt::{Display, Formatter},
+    panic::AssertUnwindSafe,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
 
-use downcast_rs::Downcast;
-use mahf::{ExecResult, SingleObjective};
-use pyo3::{exceptions::PyValueError, prelude::*};
+use eyre::{eyre, WrapErr};
+use indexmap::IndexMap;
+use mahf::ExecResult;
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyDict};
+use tracing::Span;
 use trait_set::trait_set;
 
-use crate::{experiment::Experiment, param_space::ParamSpace, scenario::Scenario};
+use crate::{
+    evaluator::{InstanceResults, TargetEvaluator},
+    experiment::{
+        experiment_span, load_instance, load_instance_at, CancellationToken, Experiment,
+        ExperimentData, InstanceError,
+    },
+    observer::{Event, ExperimentInfo, Observers},
+    param_space::ParamSpace,
+    result::{ExperimentStatus, TargetResult},
+    scenario::{PanicPolicy, Scenario, TimeoutPolicy},
+    source::ErasedInstances,
+};
+
+#[cfg(feature = "async")]
+mod asynchronous;
+mod batch;
+mod lifecycle;
+
+#[cfg(feature = "async")]
+pub use asynchronous::{AsyncBatchRunner, AsyncTargetRunner};
+pub(crate) use batch::BatchTargetRunnerWrapper;
+pub use batch::{BatchTargetRunner, ParallelTargetRunner};
+use lifecycle::Lifecycle;
 
 trait_set! {
     /// A problem instance or unique identifier.
-    pub trait Instance = Send + 'static;
-    pub(crate) trait ErasedInstance = Downcast + Send;
+    ///
+    /// Instances are shared with the threads executing experiments, and therefore need to be `Sync`.
+    pub trait Instance = Send + Sync + 'static;
 }
 
 /// Trait representing a target runner.
 ///
 /// The target runner executes some algorithm using the parameters, instance and seed
-/// provided by the [`Experiment`] and returns its performance as a single metric.
-pub trait TargetRunner<I: Instance>: Send + 'static {
-    fn run(&self, scenario: &Scenario, experiment: Experiment<I>) -> ExecResult<SingleObjective>;
+/// provided by the [`Experiment`] and returns its performance as a [`TargetResult`].
+///
+/// Closures returning anything convertible into a [`TargetResult`],
+/// e.g. a [`SingleObjective`](mahf::SingleObjective) or `f64` cost, are target runners.
+///
+/// Experiments are executed without holding the Python GIL and may run concurrently,
+/// which is why the target runner needs to be `Sync`.
+///
+/// If [`Scenario::experiment_timeout`] is set, each experiment is executed on a separate thread,
+/// which is abandoned if the time limit expires.
+/// Long-running target runners should therefore poll [`Experiment::is_cancelled`] and return early.
+///
+/// Each experiment is executed in a `tracing` span named `experiment`, carrying the configuration id,
+/// instance id and seed, which is nested in the spans of its run and the `irace` call.
+///
+/// The lifecycle hooks, e.g. [`on_tuning_start`](Self::on_tuning_start), allow to set up
+/// and tear down state of the target runner, and do nothing by default.
+/// An error returned by a hook aborts the tuning, except for
+/// [`on_new_instance`](Self::on_new_instance).
+///
+/// For runs with [`Scenario::num_jobs`] > 1 which are not batch runs, `irace` executes the
+/// experiments in forked processes. Only [`on_tuning_start`](Self::on_tuning_start) and
+/// [`on_tuning_end`](Self::on_tuning_end) are called in this process then, while the other
+/// hooks are called in the forked processes, possibly repeatedly, and their effects on the
+/// state of the target runner are lost.
+/// Use a batch run, e.g. with a [`ParallelTargetRunner`], to call all hooks in this process.
+/// Panics inside [`run`](Self::run) are handled according to [`Scenario::on_panic`],
+/// while panics inside the hooks are treated like errors returned by them.
+pub trait TargetRunner<I: Instance>: Send + Sync + 'static {
+    fn run(&self, scenario: &Scenario, experiment: Experiment<I>) -> ExecResult<TargetResult>;
+
+    /// Called once before `irace` is started.
+    #[allow(unused_variables)]
+    fn on_tuning_start(&self, scenario: &Scenario) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// Called once after `irace` returned, also if the tuning failed.
+    #[allow(unused_variables)]
+    fn on_tuning_end(&self, scenario: &Scenario) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// Called before the first experiment of the `iteration` of `irace`, starting at 1.
+    ///
+    /// Iterations are only tracked if `irace` passes the [`RaceContext::iteration`] of experiments.
+    ///
+    /// [`RaceContext::iteration`]: crate::RaceContext::iteration
+    #[allow(unused_variables)]
+    fn on_iteration_start(&self, scenario: &Scenario, iteration: u32) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// Called after the last experiment of the `iteration` of `irace`, with the ids of the
+    /// current `elites`.
+    ///
+    /// The elites are an approximation of the configurations which survived the race of the
+    /// iteration: the configurations evaluated on the most instances, ordered by their mean cost
+    /// in the iteration. `irace` itself ranks them by the statistical test of the scenario,
+    /// which may differ.
+    ///
+    /// Only called if `irace` passes the [`RaceContext::num_evaluated`] of experiments,
+    /// which is required to determine the elites.
+    ///
+    /// [`RaceContext::num_evaluated`]: crate::RaceContext::num_evaluated
+    #[allow(unused_variables)]
+    fn on_iteration_end(
+        &self,
+        scenario: &Scenario,
+        iteration: u32,
+        elites: &[String],
+    ) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// Called before the first experiment on the `instance`.
+    ///
+    /// An error fails the experiment, which is handled according to [`Scenario::on_error`],
+    /// and the hook is called again before the next experiment on the instance.
+    #[allow(unused_variables)]
+    fn on_new_instance(&self, scenario: &Scenario, instance: &I) -> eyre::Result<()> {
+        Ok(())
+    }
 }
 
 impl<I: Instance> TargetRunner<I> for Box<dyn TargetRunner<I>> {
-    fn run(&self, scenario: &Scenario, experiment: Experiment<I>) -> ExecResult<SingleObjective> {
+    fn run(&self, scenario: &Scenario, experiment: Experiment<I>) -> ExecResult<TargetResult> {
         (**self).run(scenario, experiment)
     }
+
+    fn on_tuning_start(&self, scenario: &Scenario) -> eyre::Result<()> {
+        (**self).on_tuning_start(scenario)
+    }
+
+    fn on_tuning_end(&self, scenario: &Scenario) -> eyre::Result<()> {
+        (**self).on_tuning_end(scenario)
+    }
+
+    fn on_iteration_start(&self, scenario: &Scenario, iteration: u32) -> eyre::Result<()> {
+        (**self).on_iteration_start(scenario, iteration)
+    }
+
+    fn on_iteration_end(
+        &self,
+        scenario: &Scenario,
+        iteration: u32,
+        elites: &[String],
+    ) -> eyre::Result<()> {
+        (**self).on_iteration_end(scenario, iteration, elites)
+    }
+
+    fn on_new_instance(&self, scenario: &Scenario, instance: &I) -> eyre::Result<()> {
+        (**self).on_new_instance(scenario, instance)
+    }
 }
 
-impl<I: Instance, F> TargetRunner<I> for F
+impl<I: Instance, F, R> TargetRunner<I> for F
 where
-    F: Fn(&Scenario, Experiment<I>) -> ExecResult<SingleObjective> + Send + 'static,
+    F: Fn(&Scenario, Experiment<I>) -> ExecResult<R> + Send + Sync + 'static,
+    R: Into<TargetResult>,
 {
-    fn run(&self, scenario: &Scenario, experiment: Experiment<I>) -> ExecResult<SingleObjective> {
-        (self)(scenario, experiment)
+    fn run(&self, scenario: &Scenario, experiment: Experiment<I>) -> ExecResult<TargetResult> {
+        (self)(scenario, experiment).map(Into::into)
     }
 }
 
-/// A type-erased [`TargetRunner`].
-trait ErasedTargetRunner: Send + 'static {
+/// Error representing a panic inside a [`TargetRunner`].
+#[derive(Debug)]
+pub(crate) struct RunnerPanic {
+    /// The id of the configuration the target runner panicked on.
+    pub configuration_id: String,
+    /// The parameters of the configuration.
+    pub params: String,
+    /// The panic message.
+    pub message: String,
+}
+
+impl RunnerPanic {
+    /// Extracts the panic message from the panic `payload`.
+    fn message(payload: &(dyn Any + Send)) -> String {
+        if let Some(message) = payload.downcast_ref::<&str>() {
+            message.to_string()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "unknown panic payload".to_string()
+        }
+    }
+}
+
+impl Display for RunnerPanic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "target runner panicked on configuration {} ({}): {}",
+            self.configuration_id, self.params, self.message
+        )
+    }
+}
+
+impl std::error::Error for RunnerPanic {}
+
+/// Error representing an [`Experiment`] which exceeded its time limit.
+#[derive(Debug)]
+pub(crate) struct ExperimentTimeout {
+    /// The time limit of the experiment.
+    pub timeout: Duration,
+}
+
+impl Display for ExperimentTimeout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "experiment exceeded its time limit of {:?}",
+            self.timeout
+        )
+    }
+}
+
+impl std::error::Error for ExperimentTimeout {}
+
+/// Executes the `target_runner` on the `experiment`, returning panics as [`RunnerPanic`] error.
+pub(crate) fn run_catching_panics<I: Instance>(
+    target_runner: &impl TargetRunner<I>,
+    scenario: &Scenario,
+    experiment: Experiment<I>,
+) -> ExecResult<TargetResult> {
+    let configuration_id = experiment.id.clone();
+    let params = format!("{:?}", experiment.params);
+
+    std::panic::catch_unwind(AssertUnwindSafe(|| target_runner.run(scenario, experiment)))
+        .unwrap_or_else(|payload| {
+            Err(RunnerPanic {
+                configuration_id,
+                params,
+                message: RunnerPanic::message(payload.as_ref()),
+            }
+            .into())
+        })
+}
+
+/// Calls `f`, returning a panic as error naming the panicking `hook`.
+///
+/// Protects the tuning from panics in user code besides [`TargetRunner::run`],
+/// e.g. lifecycle hooks, instance sources and target evaluators.
+pub(crate) fn catch_panics<T>(hook: &str, f: impl FnOnce() -> eyre::Result<T>) -> eyre::Result<T> {
+    std::panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or_else(|payload| {
+        Err(eyre!(
+            "{hook} panicked: {}",
+            RunnerPanic::message(payload.as_ref())
+        ))
+    })
+}
+
+/// A type-erased [`TargetRunner`] or [`BatchTargetRunner`].
+///
+/// Panics inside the target runner, including its lifecycle hooks, are caught and returned
+/// as error.
+pub(crate) trait ErasedTargetRunner: Send + Sync + 'static {
     fn run(
         &self,
         scenario: &Scenario,
-        instances: &[Box<dyn ErasedInstance>],
-        py_experiment: &PyAny,
+        instances: &dyn ErasedInstances,
+        data: &ExperimentData,
+        param_space: &ParamSpace,
+        cancellation: CancellationToken,
+    ) -> ExecResult<TargetResult>;
+
+    /// Executes a batch of experiments, by default one after another.
+    fn run_batch(
+        &self,
+        scenario: &Scenario,
+        instances: &dyn ErasedInstances,
+        data: &[ExperimentData],
         param_space: &ParamSpace,
-    ) -> ExecResult<SingleObjective>;
+    ) -> Vec<ExecResult<TargetResult>> {
+        data.iter()
+            .map(|data| {
+                self.run(
+                    scenario,
+                    instances,
+                    data,
+                    param_space,
+                    CancellationToken::new(),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns if the target runner executes batches of experiments at once.
+    fn is_batch(&self) -> bool {
+        false
+    }
+
+    fn on_tuning_start(&self, scenario: &Scenario) -> eyre::Result<()>;
+
+    fn on_tuning_end(&self, scenario: &Scenario) -> eyre::Result<()>;
+
+    fn on_iteration_start(&self, scenario: &Scenario, iteration: u32) -> eyre::Result<()>;
+
+    fn on_iteration_end(
+        &self,
+        scenario: &Scenario,
+        iteration: u32,
+        elites: &[String],
+    ) -> eyre::Result<()>;
+
+    /// Loads the instance at `index` and calls the hook of the target runner with it.
+    fn on_new_instance(
+        &self,
+        scenario: &Scenario,
+        instances: &dyn ErasedInstances,
+        index: usize,
+    ) -> eyre::Result<()>;
 }
 
 /// Wrapper to implement [`ErasedTargetRunner`] on.
-struct TargetRunnerWrapper<I: Instance>(Box<dyn TargetRunner<I>>);
+pub(crate) struct TargetRunnerWrapper<I: Instance>(pub Box<dyn TargetRunner<I>>);
 
 impl<I: Instance> ErasedTargetRunner for TargetRunnerWrapper<I> {
     fn run(
         &self,
         scenario: &Scenario,
-        instances: &[Box<dyn ErasedInstance>],
-        py_experiment: &PyAny,
+        instances: &dyn ErasedInstances,
+        data: &ExperimentData,
         param_space: &ParamSpace,
-    ) -> ExecResult<SingleObjective> {
-        let experiment = Experiment::from_py(py_experiment, instances, param_space)?;
-        self.0.run(scenario, experiment)
+        cancellation: CancellationToken,
+    ) -> ExecResult<TargetResult> {
+        let instance = load_instance::<I>(data, instances)?;
+        let experiment = Experiment::from_data(data, instance.as_ref(), param_space, cancellation);
+        run_catching_panics(&self.0, scenario, experiment)
+    }
+
+    fn on_tuning_start(&self, scenario: &Scenario) -> eyre::Result<()> {
+        catch_panics("on_tuning_start", || self.0.on_tuning_start(scenario))
+    }
+
+    fn on_tuning_end(&self, scenario: &Scenario) -> eyre::Result<()> {
+        catch_panics("on_tuning_end", || self.0.on_tuning_end(scenario))
+    }
+
+    fn on_iteration_start(&self, scenario: &Scenario, iteration: u32) -> eyre::Result<()> {
+        catch_panics("on_iteration_start", || {
+            self.0.on_iteration_start(scenario, iteration)
+        })
+    }
+
+    fn on_iteration_end(
+        &self,
+        scenario: &Scenario,
+        iteration: u32,
+        elites: &[String],
+    ) -> eyre::Result<()> {
+        catch_panics("on_iteration_end", || {
+            self.0.on_iteration_end(scenario, iteration, elites)
+        })
+    }
+
+    fn on_new_instance(
+        &self,
+        scenario: &Scenario,
+        instances: &dyn ErasedInstances,
+        index: usize,
+    ) -> eyre::Result<()> {
+        let instance = load_instance_at::<I>(index, instances)?;
+        catch_panics("on_new_instance", || {
+            self.0.on_new_instance(scenario, instance.as_ref())
+        })
+    }
+}
+
+/// Statistics collected while executing a [`TargetRunner`].
+#[derive(Default)]
+pub(crate) struct RunnerStats {
+    /// The number of failed experiments for each configuration id.
+    failures: Mutex<IndexMap<String, u32>>,
+    /// The worst cost seen on each instance.
+    worst_costs: Mutex<HashMap<String, f64>>,
+    /// The results per instance class, if the instances have classes.
+    class_results: Mutex<IndexMap<String, ClassResults>>,
+    /// The number of experiment threads which were abandoned after their time limit
+    /// expired and are still running.
+    abandoned_threads: AtomicU32,
+}
+
+/// The results of all experiments on instances of a class, see [`TuningStats::class_results`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClassResults {
+    /// The number of executed experiments.
+    pub experiments: u32,
+    /// The number of failed experiments, i.e. experiments whose status is not
+    /// [`ExperimentStatus::Ok`].
+    pub failures: u32,
+    /// The sum of the costs reported to `irace`, including penalty costs.
+    pub total_cost: f64,
+}
+
+impl ClassResults {
+    /// Returns the mean cost reported to `irace`, including penalty costs.
+    pub fn mean_cost(&self) -> f64 {
+        self.total_cost / self.experiments as f64
+    }
+}
+
+impl RunnerStats {
+    fn record_cost(&self, instance_id: &str, cost: f64) {
+        let mut worst_costs = self.worst_costs.lock().unwrap();
+        let worst = worst_costs.entry(instance_id.to_owned()).or_insert(cost);
+        *worst = worst.max(cost);
+    }
+
+    fn worst_cost(&self, instance_id: &str) -> Option<f64> {
+        self.worst_costs.lock().unwrap().get(instance_id).copied()
+    }
+
+    fn record_failure(&self, configuration_id: &str) {
+        *self
+            .failures
+            .lock()
+            .unwrap()
+            .entry(configuration_id.to_owned())
+            .or_default() += 1;
+    }
+
+    /// Records that an experiment thread was abandoned and returns the number of
+    /// abandoned threads which are still running.
+    fn abandon_thread(&self) -> u32 {
+        self.abandoned_threads.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    /// Records that an abandoned experiment thread terminated.
+    fn finish_abandoned_thread(&self) {
+        self.abandoned_threads.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn record_class_result(&self, class: &str, result: &TargetResult) {
+        let mut class_results = self.class_results.lock().unwrap();
+        let results = class_results.entry(class.to_owned()).or_default();
+        results.experiments += 1;
+        results.total_cost += result.cost;
+        if !result.status.is_ok() {
+            results.failures += 1;
+        }
+    }
+
+    /// Returns the statistics collected so far.
+    pub fn snapshot(&self) -> TuningStats {
+        TuningStats {
+            failures: self.failures.lock().unwrap().clone(),
+            class_results: self.class_results.lock().unwrap().clone(),
+            abandoned_threads: self.abandoned_threads.load(Ordering::SeqCst),
+        }
+    }
+}
+
+/// Statistics of all experiments of a tuning, see [`Event::TuningFinished`].
+///
+/// Only experiments executed in this process are counted. For runs with
+/// [`Scenario::num_jobs`] > 1 which are not batch runs, `irace` executes the experiments
+/// in forked processes, so the statistics are empty.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TuningStats {
+    /// The number of failed experiments for each configuration id, i.e. experiments reported
+    /// to `irace` with a penalty cost.
+    pub failures: IndexMap<String, u32>,
+    /// The results per instance class, empty if the instances have no classes,
+    /// see [`Run::with_instance_features`].
+    ///
+    /// [`Run::with_instance_features`]: crate::Run::with_instance_features
+    pub class_results: IndexMap<String, ClassResults>,
+    /// The number of experiment threads which were abandoned after their time limit expired
+    /// and were still running when the statistics were taken.
+    ///
+    /// Abandoned threads keep consuming resources until the target runner returns,
+    /// so a large number indicates that the target runner does not poll its
+    /// [`CancellationToken`].
+    pub abandoned_threads: u32,
+}
+
+impl TuningStats {
+    /// Returns the total number of failed experiments.
+    pub fn num_failures(&self) -> u32 {
+        self.failures.values().sum()
     }
 }
 
 /// Wraps all necessary data to execute a [`TargetRunner`] inside a Python object.
+///
+/// The target runner is executed without holding the GIL, so the wrapper may be called
+/// concurrently from multiple Python threads.
 #[pyclass(name = "TargetRunner")]
 pub(crate) struct PyTargetRunner {
-    runner: Box<dyn ErasedTargetRunner>,
-    instances: Vec<Box<dyn ErasedInstance>>,
+    runner: Arc<dyn ErasedTargetRunner>,
+    instances: Arc<dyn ErasedInstances>,
+    /// The index of each instance by name, if the instances are named.
+    instance_indices: HashMap<String, usize>,
+    /// The class of each instance by index, if the instances have classes.
+    instance_classes: Vec<Option<String>>,
     scenario: Arc<Scenario>,
     param_space: Arc<ParamSpace>,
+    stats: Arc<RunnerStats>,
+    evaluator: Option<Arc<dyn TargetEvaluator>>,
+    /// The results of all experiments per instance id and seed, only recorded for the evaluator.
+    ///
+    /// The results are kept in-process, so the evaluator requires experiments to be executed
+    /// in this process, i.e. `num_jobs` = 1 or a batch run, see [`Run::with_evaluator`].
+    ///
+    /// [`Run::with_evaluator`]: crate::Run::with_evaluator
+    instance_results: Mutex<HashMap<(String, u64), InstanceResults>>,
+    lifecycle: Lifecycle,
+    /// The span of the run, which is the parent of all experiment spans.
+    span: Span,
 }
 
 impl PyTargetRunner {
     /// Constructs a new `PyTargetRunner`.
-    pub fn new<F, I: Instance>(
-        runner: F,
-        instances: Vec<I>,
+    pub fn new(
+        runner: Arc<dyn ErasedTargetRunner>,
+        instances: Arc<dyn ErasedInstances>,
+        instance_names: Option<&[String]>,
         scenario: Arc<Scenario>,
         param_space: Arc<ParamSpace>,
-    ) -> Self
-    where
-        F: TargetRunner<I>,
-    {
+        evaluator: Option<Arc<dyn TargetEvaluator>>,
+    ) -> Self {
         Self {
-            runner: Box::new(TargetRunnerWrapper(Box::new(runner))),
-            instances: instances
+            runner,
+            instances,
+            instance_indices: instance_names
                 .into_iter()
-                .map(|instance| Box::new(instance) as Box<dyn ErasedInstance>)
+                .flatten()
+                .enumerate()
+                .map(|(index, name)| (name.clone(), index))
                 .collect(),
+            instance_classes: Vec::new(),
             scenario,
             param_space,
+            stats: Default::default(),
+            evaluator,
+            instance_results: Default::default(),
+            lifecycle: Default::default(),
+            span: Span::none(),
+        }
+    }
+
+    /// Executes the experiments in the `span` of the run.
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
+
+    /// Notifies the `observers` of the progress of the tuning.
+    pub fn with_observers(mut self, observers: Observers) -> Self {
+        self.lifecycle = Lifecycle::new(observers);
+        self
+    }
+
+    /// Breaks down the results by the `classes` of the instances, given by index.
+    pub fn with_instance_classes(mut self, classes: Vec<Option<String>>) -> Self {
+        self.instance_classes = classes;
+        self
+    }
+
+    /// Calls the hook of the target runner before `irace` is started.
+    pub fn start_tuning(&self) -> eyre::Result<()> {
+        let _entered = self.span.enter();
+        self.runner.on_tuning_start(&self.scenario)
+    }
+
+    /// Calls the hooks of the target runner after `irace` returned, and notifies the observers
+    /// of the statistics of the tuning.
+    pub fn end_tuning(&self) -> eyre::Result<()> {
+        let _entered = self.span.enter();
+        let result = self
+            .lifecycle
+            .end_tuning(self.runner.as_ref(), &self.scenario);
+        self.lifecycle
+            .observers()
+            .notify(Event::TuningFinished(self.stats.snapshot()));
+        result
+    }
+
+    /// Returns the statistics collected while executing the target runner.
+    pub fn stats(&self) -> Arc<RunnerStats> {
+        self.stats.clone()
+    }
+
+    /// Returns if the target runner executes batches of experiments at once.
+    pub fn is_batch(&self) -> bool {
+        self.runner.is_batch()
+    }
+
+    /// Returns if the costs are computed by a [`TargetEvaluator`].
+    pub fn has_evaluator(&self) -> bool {
+        self.evaluator.is_some()
+    }
+
+    /// Executes the target runner once.
+    ///
+    /// If the scenario specifies a time limit, the experiment is executed on a separate thread,
+    /// which is abandoned if the time limit expires.
+    fn run_once(&self, data: &ExperimentData) -> ExecResult<TargetResult> {
+        let Some(timeout) = self.scenario.experiment_timeout else {
+            return self.runner.run(
+                &self.scenario,
+                self.instances.as_ref(),
+                data,
+                &self.param_space,
+                CancellationToken::new(),
+            );
+        };
+
+        let cancellation = CancellationToken::with_deadline(Instant::now() + timeout);
+        let (sender, receiver) = mpsc::channel();
+        {
+            let runner = self.runner.clone();
+            let instances = self.instances.clone();
+            let scenario = self.scenario.clone();
+            let param_space = self.param_space.clone();
+            let data = data.clone();
+            let cancellation = cancellation.clone();
+            let stats = self.stats.clone();
+            let span = Span::current();
+
+            std::thread::Builder::new()
+                .name(format!("experiment-{}", data.id))
+                .spawn(move || {
+                    let _entered = span.enter();
+                    let result = runner.run(
+                        &scenario,
+                        instances.as_ref(),
+                        &data,
+                        &param_space,
+                        cancellation,
+                    );
+                    // The receiver is already dropped if the experiment timed out,
+                    // i.e. after the thread was counted as abandoned.
+                    if sender.send(result).is_err() {
+                        stats.finish_abandoned_thread();
+                    }
+                })
+                .wrap_err("failed to spawn experiment thread")?;
+        }
+
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                let abandoned_threads = self.stats.abandon_thread();
+                tracing::warn!(
+                    abandoned_threads,
+                    "experiment exceeded its time limit, abandoning its still running thread"
+                );
+                cancellation.cancel();
+                Err(ExperimentTimeout { timeout }.into())
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(eyre!("experiment thread terminated unexpectedly"))
+            }
+        }
+    }
+
+    /// Executes a batch of experiments at once.
+    ///
+    /// Batch target runners are responsible for respecting the time limit of the scenario
+    /// themselves. As a safeguard, the batch is executed on a separate thread, which is
+    /// abandoned if the batch takes longer than executing all experiments with
+    /// [`Scenario::num_jobs`] parallel jobs at their time limit would. All experiments of
+    /// an abandoned batch are reported as timed out.
+    fn run_batch_once(&self, data: &[ExperimentData]) -> Vec<ExecResult<TargetResult>> {
+        let Some(timeout) = self.scenario.experiment_timeout else {
+            return self.runner.run_batch(
+                &self.scenario,
+                self.instances.as_ref(),
+                data,
+                &self.param_space,
+            );
+        };
+
+        let num_rounds = data.len().div_ceil(self.scenario.num_jobs.max(1)).max(1);
+        let batch_timeout = timeout * num_rounds as u32;
+        let (sender, receiver) = mpsc::channel();
+        let spawned = {
+            let runner = self.runner.clone();
+            let instances = self.instances.clone();
+            let scenario = self.scenario.clone();
+            let param_space = self.param_space.clone();
+            let data = data.to_vec();
+            let stats = self.stats.clone();
+            let span = Span::current();
+
+            std::thread::Builder::new()
+                .name("experiment-batch".to_owned())
+                .spawn(move || {
+                    let _entered = span.enter();
+                    let results =
+                        runner.run_batch(&scenario, instances.as_ref(), &data, &param_space);
+                    // The receiver is already dropped if the batch timed out,
+                    // i.e. after the thread was counted as abandoned.
+                    if sender.send(results).is_err() {
+                        stats.finish_abandoned_thread();
+                    }
+                })
+                .wrap_err("failed to spawn experiment batch thread")
+        };
+        if let Err(e) = spawned {
+            return data.iter().map(|_| Err(eyre!("{e:#}"))).collect();
+        }
+
+        match receiver.recv_timeout(batch_timeout) {
+            Ok(results) => results,
+            Err(RecvTimeoutError::Timeout) => {
+                let abandoned_threads = self.stats.abandon_thread();
+                tracing::warn!(
+                    abandoned_threads,
+                    "experiment batch exceeded its time limit, abandoning its still running thread"
+                );
+                data.iter()
+                    .map(|_| Err(ExperimentTimeout { timeout }.into()))
+                    .collect()
+            }
+            Err(RecvTimeoutError::Disconnected) => data
+                .iter()
+                .map(|_| Err(eyre!("experiment batch thread terminated unexpectedly")))
+                .collect(),
+        }
+    }
+
+    /// Executes a single attempt of an experiment, calling the instance hook first if needed.
+    ///
+    /// If the target runner does not report the time, the elapsed time of the target runner
+    /// is used, excluding the instance hook.
+    fn attempt(&self, data: &ExperimentData) -> ExecResult<TargetResult> {
+        self.lifecycle.prepare_instance(
+            self.runner.as_ref(),
+            &self.scenario,
+            self.instances.as_ref(),
+            data,
+        )?;
+
+        let start = Instant::now();
+        let mut result = self.run_once(data)?;
+        result.time.get_or_insert_with(|| start.elapsed());
+        Ok(result)
+    }
+
+    /// Executes the target runner, applying the failure, panic and timeout policies of the scenario.
+    fn execute(&self, data: &ExperimentData) -> ExecResult<TargetResult> {
+        let span = experiment_span(&self.span, &data.id, data.instance_id.as_deref(), data.seed);
+        let _entered = span.enter();
+
+        self.lifecycle.before_experiments(
+            self.runner.as_ref(),
+            &self.scenario,
+            std::slice::from_ref(data),
+        )?;
+        self.notify_started(std::slice::from_ref(data));
+
+        let start = Instant::now();
+        let result = self.attempt(data);
+        let result = self.apply_policies(data, result);
+        self.notify_finished(data, &result, Some(start.elapsed()));
+
+        let result = result?;
+        self.record_result(data, &result);
+        Ok(result)
+    }
+
+    /// Executes the target runner on a batch of experiments at once,
+    /// applying the policies of the scenario to each result.
+    ///
+    /// Experiments whose instance hook fails are not executed, and all failed experiments
+    /// are retried one at a time.
+    fn execute_batch(&self, data: &[ExperimentData]) -> ExecResult<Vec<TargetResult>> {
+        // The experiment spans are created by the batch target runner, e.g. `ParallelTargetRunner`.
+        let _entered = self.span.enter();
+
+        self.lifecycle
+            .before_experiments(self.runner.as_ref(), &self.scenario, data)?;
+        self.notify_started(data);
+
+        let mut results = Vec::with_capacity(data.len());
+        let mut prepared = Vec::with_capacity(data.len());
+        for data in data {
+            let hook = self.lifecycle.prepare_instance(
+                self.runner.as_ref(),
+                &self.scenario,
+                self.instances.as_ref(),
+                data,
+            );
+            match hook {
+                Ok(()) => {
+                    prepared.push(data.clone());
+                    results.push(None);
+                }
+                Err(e) => results.push(Some(Err(e))),
+            }
+        }
+
+        let mut batch_results = self.run_batch_once(&prepared).into_iter();
+        results
+            .into_iter()
+            .zip(data)
+            .map(|(result, data)| {
+                let result = result.unwrap_or_else(|| {
+                    batch_results
+                        .next()
+                        .expect("the batch returns a result for each experiment")
+                });
+                let result = self.apply_policies(data, result);
+                // The duration of a single experiment of a batch is only known if it is reported.
+                let duration = result.as_ref().ok().and_then(|result| result.time);
+                self.notify_finished(data, &result, duration);
+
+                let result = result?;
+                self.record_result(data, &result);
+                Ok(result)
+            })
+            .collect()
+    }
+
+    /// Notifies the observers that the experiments of `data` are about to be executed.
+    fn notify_started(&self, data: &[ExperimentData]) {
+        let observers = self.lifecycle.observers();
+        if observers.is_empty() {
+            return;
+        }
+
+        for data in data {
+            observers.notify(Event::ExperimentStarted(ExperimentInfo::new(data)));
         }
     }
+
+    /// Notifies the observers that the experiment of `data` finished with the `result`.
+    fn notify_finished(
+        &self,
+        data: &ExperimentData,
+        result: &ExecResult<TargetResult>,
+        duration: Option<Duration>,
+    ) {
+        let observers = self.lifecycle.observers();
+        if observers.is_empty() {
+            return;
+        }
+
+        observers.notify(Event::ExperimentFinished {
+            experiment: ExperimentInfo::new(data),
+            result: result
+                .as_ref()
+                .map(Clone::clone)
+                .map_err(|e| format!("{e:#}")),
+            duration,
+        });
+    }
+
+    /// Records the `result` of an experiment for the lifecycle hooks, the class of its instance
+    /// and the target evaluator, if any.
+    fn record_result(&self, data: &ExperimentData, result: &TargetResult) {
+        self.lifecycle.record_result(data, result);
+
+        let class = data
+            .instance_index
+            .and_then(|index| self.instance_classes.get(index))
+            .and_then(Option::as_deref);
+        if let Some(class) = class {
+            self.stats.record_class_result(class, result);
+        }
+
+        if self.evaluator.is_none() {
+            return;
+        }
+
+        let instance_id = data.instance_id.clone().unwrap_or_default();
+        self.instance_results
+            .lock()
+            .unwrap()
+            .entry((instance_id.clone(), data.seed))
+            .or_insert_with(|| InstanceResults::new(instance_id, data.seed))
+            .costs
+            .insert(data.id.clone(), result.cost);
+    }
+
+    /// Computes the cost of an experiment using the target evaluator.
+    ///
+    /// Only the results of the `configuration_ids` in the race are passed to the evaluator,
+    /// and the results of all other configurations on the instance are discarded,
+    /// as they were eliminated and won't be evaluated again.
+    fn evaluate_cost(
+        &self,
+        data: &ExperimentData,
+        configuration_ids: &HashSet<String>,
+    ) -> ExecResult<f64> {
+        let evaluator = self
+            .evaluator
+            .as_ref()
+            .ok_or_else(|| eyre!("no target evaluator specified"))?;
+
+        let instance_id = data.instance_id.clone().unwrap_or_default();
+        let mut instance_results = self.instance_results.lock().unwrap();
+        let results = instance_results
+            .get_mut(&(instance_id, data.seed))
+            .ok_or_else(|| {
+                eyre!(
+                    "no results on instance {:?} with seed {}",
+                    data.instance_id,
+                    data.seed
+                )
+            })?;
+        results
+            .costs
+            .retain(|configuration_id, _| configuration_ids.contains(configuration_id));
+        catch_panics("target evaluator", || {
+            evaluator.evaluate(&self.scenario, &data.id, results)
+        })
+    }
+
+    /// Applies the failure, panic and timeout policies of the scenario to the `result`
+    /// of the first attempt of an experiment.
+    fn apply_policies(
+        &self,
+        data: &ExperimentData,
+        mut result: ExecResult<TargetResult>,
+    ) -> ExecResult<TargetResult> {
+        let instance_id = data.instance_id.clone().unwrap_or_default();
+
+        let policy = self.scenario.on_error;
+        let mut attempt = 0;
+        let error = loop {
+            match result {
+                Ok(result) => {
+                    self.stats.record_cost(&instance_id, result.cost);
+                    return Ok(result);
+                }
+                // Unresolvable instances are a bug of the run, which no retry or penalty fixes.
+                Err(e) if e.is::<InstanceError>() => return Err(e),
+                Err(e) if e.is::<RunnerPanic>() || e.is::<ExperimentTimeout>() => break e,
+                Err(_) if attempt < policy.retries => {
+                    attempt += 1;
+                    result = self.attempt(data);
+                }
+                Err(e) => break e,
+            }
+        };
+
+        let worst_cost = self.stats.worst_cost(&instance_id);
+        let (status, cost, time) = if let Some(timeout) = error.downcast_ref::<ExperimentTimeout>()
+        {
+            let cost = match self.scenario.on_timeout {
+                None => return Err(error),
+                Some(TimeoutPolicy::Censored) => timeout.timeout.as_secs_f64(),
+                Some(TimeoutPolicy::Penalty(penalty)) => penalty.cost(worst_cost),
+            };
+            (ExperimentStatus::Timeout, cost, Some(timeout.timeout))
+        } else if error.is::<RunnerPanic>() {
+            match self.scenario.on_panic {
+                PanicPolicy::Abort => return Err(error),
+                PanicPolicy::Penalty(penalty) => {
+                    (ExperimentStatus::Panicked, penalty.cost(worst_cost), None)
+                }
+            }
+        } else {
+            match policy.penalty {
+                None => return Err(error),
+                Some(penalty) => (ExperimentStatus::Failed, penalty.cost(worst_cost), None),
+            }
+        };
+
+        self.stats.record_failure(&data.id);
+        let mut result = TargetResult::new(cost).with_metadata("error", format!("{error:#}"));
+        result.time = time;
+        result.status = status;
+        Ok(result)
+    }
 }
 
 #[pymethods]
 impl PyTargetRunner {
     #[pyo3(signature = (scenario, experiment))]
     #[allow(unused_variables)]
-    fn __call__(&self, py: Python<'_>, scenario: PyObject, experiment: PyObject) -> PyResult<f64> {
-        self.runner
-            .run(
-                &self.scenario,
-                self.instances.as_slice(),
-                experiment.as_ref(py),
-                &self.param_space,
-            )
-            .map_err(|e| PyValueError::new_err(e.to_string()))
-            .map(|result| result.value())
+    fn __call__(
+        &self,
+        py: Python<'_>,
+        scenario: PyObject,
+        experiment: PyObject,
+    ) -> PyResult<PyObject> {
+        // Convert the experiment while holding the GIL, and release it for the execution,
+        // which allows multiple experiments to run in parallel.
+        let data = ExperimentData::from_py(
+            experiment.as_ref(py),
+            &self.param_space,
+            &self.instance_indices,
+        )?;
+
+        let result = py.allow_threads(|| self.execute(&data));
+
+        result
+            .map_err(|e| PyValueError::new_err(format!("{e:#}")))?
+            .as_py_object(py)
+    }
+
+    /// Executes a batch of experiments at once, see [`BatchTargetRunner`].
+    ///
+    /// The results are in the same order as the `experiments`.
+    #[pyo3(signature = (scenario, experiments))]
+    #[allow(unused_variables)]
+    fn run_batch(
+        &self,
+        py: Python<'_>,
+        scenario: PyObject,
+        experiments: &PyAny,
+    ) -> PyResult<Vec<PyObject>> {
+        let data = experiments
+            .iter()?
+            .map(|experiment| {
+                ExperimentData::from_py(experiment?, &self.param_space, &self.instance_indices)
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+
+        // The time of experiments is only reported if the batch target runner measured it,
+        // as the elapsed time of the whole batch is not the time of a single experiment.
+        let results = py.allow_threads(|| self.execute_batch(&data));
+
+        results
+            .map_err(|e| PyValueError::new_err(format!("{e:#}")))?
+            .iter()
+            .map(|result| result.as_py_object(py))
+            .collect()
+    }
+
+    /// Computes the cost of an experiment after all configurations were executed on its instance,
+    /// see [`TargetEvaluator`].
+    #[pyo3(signature = (scenario, experiment, num_configurations, all_conf_id))]
+    #[allow(unused_variables)]
+    fn evaluate(
+        &self,
+        py: Python<'_>,
+        scenario: PyObject,
+        experiment: PyObject,
+        num_configurations: PyObject,
+        all_conf_id: PyObject,
+    ) -> PyResult<PyObject> {
+        let data = ExperimentData::from_py(
+            experiment.as_ref(py),
+            &self.param_space,
+            &self.instance_indices,
+        )?;
+
+        let configuration_ids = all_conf_id
+            .as_ref(py)
+            .iter()?
+            .map(|id| {
+                let id = id?;
+                // Configuration ids are passed as strings or integers, depending on the wrapper.
+                id.extract::<String>()
+                    .or_else(|_| id.extract::<i64>().map(|id| id.to_string()))
+            })
+            .collect::<PyResult<HashSet<_>>>()?;
+
+        let cost = py
+            .allow_threads(|| self.evaluate_cost(&data, &configuration_ids))
+            .map_err(|e| PyValueError::new_err(format!("{e:#}")))?;
+
+        let dict = PyDict::new(py);
+        dict.set_item("cost", cost)?;
+        Ok(dict.to_object(py))
     }
 }
@@ -0,0 +1,270 @@
+//! Executing asynchronous target runners.
+
+use std::{panic::AssertUnwindSafe, time::Instant};
+
+use eyre::WrapErr;
+use futures::{future::BoxFuture, stream, FutureExt, StreamExt};
+use mahf::ExecResult;
+use tokio::runtime::{Handle, Runtime, RuntimeFlavor};
+use tracing::{Instrument, Span};
+
+use crate::{
+    experiment::{experiment_span, CancellationToken, Experiment},
+    result::TargetResult,
+    runner::{BatchTargetRunner, ExperimentTimeout, Instance, RunnerPanic},
+    scenario::Scenario,
+};
+
+/// Trait representing an asynchronous target runner.
+///
+/// Like a [`TargetRunner`](crate::TargetRunner), but returns a future,
+/// which allows many experiments to be in flight at once without one thread per experiment.
+/// Use [`AsyncBatchRunner`] to execute it.
+///
+/// The lifecycle hooks are called like those of a [`TargetRunner`](crate::TargetRunner).
+pub trait AsyncTargetRunner<I: Instance>: Send + Sync + 'static {
+    fn run<'a>(
+        &'a self,
+        scenario: &'a Scenario,
+        experiment: Experiment<'a, I>,
+    ) -> BoxFuture<'a, ExecResult<TargetResult>>;
+
+    /// See [`TargetRunner::on_tuning_start`](crate::TargetRunner::on_tuning_start).
+    #[allow(unused_variables)]
+    fn on_tuning_start(&self, scenario: &Scenario) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// See [`TargetRunner::on_tuning_end`](crate::TargetRunner::on_tuning_end).
+    #[allow(unused_variables)]
+    fn on_tuning_end(&self, scenario: &Scenario) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// See [`TargetRunner::on_iteration_start`](crate::TargetRunner::on_iteration_start).
+    #[allow(unused_variables)]
+    fn on_iteration_start(&self, scenario: &Scenario, iteration: u32) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// See [`TargetRunner::on_iteration_end`](crate::TargetRunner::on_iteration_end).
+    #[allow(unused_variables)]
+    fn on_iteration_end(
+        &self,
+        scenario: &Scenario,
+        iteration: u32,
+        elites: &[String],
+    ) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// See [`TargetRunner::on_new_instance`](crate::TargetRunner::on_new_instance).
+    #[allow(unused_variables)]
+    fn on_new_instance(&self, scenario: &Scenario, instance: &I) -> eyre::Result<()> {
+        Ok(())
+    }
+}
+
+impl<I: Instance> AsyncTargetRunner<I> for Box<dyn AsyncTargetRunner<I>> {
+    fn run<'a>(
+        &'a self,
+        scenario: &'a Scenario,
+        experiment: Experiment<'a, I>,
+    ) -> BoxFuture<'a, ExecResult<TargetResult>> {
+        (**self).run(scenario, experiment)
+    }
+
+    fn on_tuning_start(&self, scenario: &Scenario) -> eyre::Result<()> {
+        (**self).on_tuning_start(scenario)
+    }
+
+    fn on_tuning_end(&self, scenario: &Scenario) -> eyre::Result<()> {
+        (**self).on_tuning_end(scenario)
+    }
+
+    fn on_iteration_start(&self, scenario: &Scenario, iteration: u32) -> eyre::Result<()> {
+        (**self).on_iteration_start(scenario, iteration)
+    }
+
+    fn on_iteration_end(
+        &self,
+        scenario: &Scenario,
+        iteration: u32,
+        elites: &[String],
+    ) -> eyre::Result<()> {
+        (**self).on_iteration_end(scenario, iteration, elites)
+    }
+
+    fn on_new_instance(&self, scenario: &Scenario, instance: &I) -> eyre::Result<()> {
+        (**self).on_new_instance(scenario, instance)
+    }
+}
+
+/// The runtime an [`AsyncBatchRunner`] executes its futures on.
+enum AsyncRuntime {
+    Owned(Runtime),
+    Handle(Handle),
+}
+
+impl AsyncRuntime {
+    fn handle(&self) -> &Handle {
+        match self {
+            AsyncRuntime::Owned(runtime) => runtime.handle(),
+            AsyncRuntime::Handle(handle) => handle,
+        }
+    }
+}
+
+/// Adapter to execute an [`AsyncTargetRunner`] as [`BatchTargetRunner`].
+///
+/// All experiments of a batch are executed concurrently on a `tokio` runtime,
+/// which is either managed by the adapter or provided by the user.
+///
+/// Panics are caught per experiment, and each future is instrumented with its own `tracing` span.
+/// If [`Scenario::experiment_timeout`] is set, the future of an experiment is dropped
+/// when its time limit expires, and the experiment is reported as timed out.
+///
+/// By default, all experiments of a batch are in flight at once, which can be limited
+/// using [`with_max_concurrency`](Self::with_max_concurrency).
+pub struct AsyncBatchRunner<R> {
+    target_runner: R,
+    runtime: AsyncRuntime,
+    max_concurrency: Option<usize>,
+}
+
+impl<R> AsyncBatchRunner<R> {
+    /// Constructs a new `AsyncBatchRunner` executing the `target_runner` on its own
+    /// multi-threaded runtime.
+    pub fn new(target_runner: R) -> eyre::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("irace-async")
+            .build()
+            .wrap_err("failed to build async runtime")?;
+
+        Ok(Self {
+            target_runner,
+            runtime: AsyncRuntime::Owned(runtime),
+            max_concurrency: None,
+        })
+    }
+
+    /// Constructs a new `AsyncBatchRunner` executing the `target_runner` on the runtime
+    /// of the `handle`.
+    ///
+    /// The runtime needs the time driver enabled if [`Scenario::experiment_timeout`] is set.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the runtime is not multi-threaded. Batches are executed by blocking the
+    /// thread calling the target runner, which would starve a current-thread runtime
+    /// driven by another thread.
+    pub fn with_handle(target_runner: R, handle: Handle) -> Self {
+        assert!(
+            handle.runtime_flavor() == RuntimeFlavor::MultiThread,
+            "`AsyncBatchRunner` requires a multi-threaded runtime"
+        );
+        Self {
+            target_runner,
+            runtime: AsyncRuntime::Handle(handle),
+            max_concurrency: None,
+        }
+    }
+
+    /// Limits the number of experiments in flight at once to `max_concurrency`, but at least one.
+    ///
+    /// Further experiments of the batch are started as soon as running ones finish,
+    /// and their time limit starts when they are started.
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = Some(max_concurrency.max(1));
+        self
+    }
+}
+
+impl<I: Instance, R: AsyncTargetRunner<I>> BatchTargetRunner<I> for AsyncBatchRunner<R> {
+    fn run_batch(
+        &self,
+        scenario: &Scenario,
+        experiments: Vec<Experiment<I>>,
+    ) -> Vec<ExecResult<TargetResult>> {
+        let parent = Span::current();
+        let futures = experiments.into_iter().map(|mut experiment| {
+            let span = experiment_span(
+                &parent,
+                &experiment.id,
+                experiment.instance_id.as_deref(),
+                experiment.seed,
+            );
+            async move {
+                let start = Instant::now();
+                let timeout = scenario.experiment_timeout;
+                if let (Some(timeout), None) = (timeout, experiment.cancellation.deadline()) {
+                    experiment.cancellation = CancellationToken::with_deadline(start + timeout);
+                }
+
+                let configuration_id = experiment.id.clone();
+                let params = format!("{:?}", experiment.params);
+                // The target runner is called inside the future, so panics while creating
+                // the future are caught as well as panics while polling it.
+                let run = async move { self.target_runner.run(scenario, experiment).await };
+                let future = AssertUnwindSafe(run).catch_unwind().map(|result| {
+                    result.unwrap_or_else(|payload| {
+                        Err(RunnerPanic {
+                            configuration_id,
+                            params,
+                            message: RunnerPanic::message(payload.as_ref()),
+                        }
+                        .into())
+                    })
+                });
+
+                let result = match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, future)
+                        .await
+                        .unwrap_or_else(|_| Err(ExperimentTimeout { timeout }.into())),
+                    None => future.await,
+                };
+                result.map(|mut result| {
+                    result.time.get_or_insert_with(|| start.elapsed());
+                    result
+                })
+            }
+            .instrument(span)
+        });
+
+        let handle = self.runtime.handle();
+        match self.max_concurrency {
+            // `buffered` preserves the order of the experiments.
+            Some(max_concurrency) => {
+                handle.block_on(stream::iter(futures).buffered(max_concurrency).collect())
+            }
+            None => handle.block_on(futures::future::join_all(futures)),
+        }
+    }
+
+    fn on_tuning_start(&self, scenario: &Scenario) -> eyre::Result<()> {
+        self.target_runner.on_tuning_start(scenario)
+    }
+
+    fn on_tuning_end(&self, scenario: &Scenario) -> eyre::Result<()> {
+        self.target_runner.on_tuning_end(scenario)
+    }
+
+    fn on_iteration_start(&self, scenario: &Scenario, iteration: u32) -> eyre::Result<()> {
+        self.target_runner.on_iteration_start(scenario, iteration)
+    }
+
+    fn on_iteration_end(
+        &self,
+        scenario: &Scenario,
+        iteration: u32,
+        elites: &[String],
+    ) -> eyre::Result<()> {
+        self.target_runner
+            .on_iteration_end(scenario, iteration, elites)
+    }
+
+    fn on_new_instance(&self, scenario: &Scenario, instance: &I) -> eyre::Result<()> {
+        self.target_runner.on_new_instance(scenario, instance)
+    }
+}
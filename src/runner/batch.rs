@@ -0,0 +1,475 @@
+//! Executing batches of experiments at once.
+
+use std::{panic::AssertUnwindSafe, sync::Mutex, time::Instant};
+
+use eyre::eyre;
+use mahf::ExecResult;
+use tracing::Span;
+
+use crate::{
+    experiment::{
+        experiment_span, load_instance, load_instance_at, CancellationToken, Experiment,
+        ExperimentData,
+    },
+    param_space::ParamSpace,
+    result::TargetResult,
+    runner::{
+        catch_panics, run_catching_panics, ErasedTargetRunner, ExperimentTimeout, Instance,
+        RunnerPanic, TargetRunner,
+    },
+    scenario::Scenario,
+    source::ErasedInstances,
+};
+
+/// Trait representing a target runner which executes a batch of experiments at once.
+///
+/// This corresponds to the `targetRunnerParallel` option of `irace`: all experiments of a race step
+/// are passed at once, and the target runner is responsible for executing them, e.g. in parallel.
+/// Use [`ParallelTargetRunner`] to execute any [`TargetRunner`] on a thread pool.
+///
+/// The returned results must be in the same order as the `experiments`.
+///
+/// Experiments are passed without deadline, i.e. batch target runners are responsible for
+/// respecting [`Scenario::experiment_timeout`] themselves.
+///
+/// The lifecycle hooks are called like those of a [`TargetRunner`].
+pub trait BatchTargetRunner<I: Instance>: Send + Sync + 'static {
+    fn run_batch(
+        &self,
+        scenario: &Scenario,
+        experiments: Vec<Experiment<I>>,
+    ) -> Vec<ExecResult<TargetResult>>;
+
+    /// See [`TargetRunner::on_tuning_start`].
+    #[allow(unused_variables)]
+    fn on_tuning_start(&self, scenario: &Scenario) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// See [`TargetRunner::on_tuning_end`].
+    #[allow(unused_variables)]
+    fn on_tuning_end(&self, scenario: &Scenario) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// See [`TargetRunner::on_iteration_start`].
+    #[allow(unused_variables)]
+    fn on_iteration_start(&self, scenario: &Scenario, iteration: u32) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// See [`TargetRunner::on_iteration_end`].
+    #[allow(unused_variables)]
+    fn on_iteration_end(
+        &self,
+        scenario: &Scenario,
+        iteration: u32,
+        elites: &[String],
+    ) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    /// See [`TargetRunner::on_new_instance`].
+    #[allow(unused_variables)]
+    fn on_new_instance(&self, scenario: &Scenario, instance: &I) -> eyre::Result<()> {
+        Ok(())
+    }
+}
+
+impl<I: Instance> BatchTargetRunner<I> for Box<dyn BatchTargetRunner<I>> {
+    fn run_batch(
+        &self,
+        scenario: &Scenario,
+        experiments: Vec<Experiment<I>>,
+    ) -> Vec<ExecResult<TargetResult>> {
+        (**self).run_batch(scenario, experiments)
+    }
+
+    fn on_tuning_start(&self, scenario: &Scenario) -> eyre::Result<()> {
+        (**self).on_tuning_start(scenario)
+    }
+
+    fn on_tuning_end(&self, scenario: &Scenario) -> eyre::Result<()> {
+        (**self).on_tuning_end(scenario)
+    }
+
+    fn on_iteration_start(&self, scenario: &Scenario, iteration: u32) -> eyre::Result<()> {
+        (**self).on_iteration_start(scenario, iteration)
+    }
+
+    fn on_iteration_end(
+        &self,
+        scenario: &Scenario,
+        iteration: u32,
+        elites: &[String],
+    ) -> eyre::Result<()> {
+        (**self).on_iteration_end(scenario, iteration, elites)
+    }
+
+    fn on_new_instance(&self, scenario: &Scenario, instance: &I) -> eyre::Result<()> {
+        (**self).on_new_instance(scenario, instance)
+    }
+}
+
+/// Adapter to execute a [`TargetRunner`] as [`BatchTargetRunner`] on a thread pool
+/// with [`Scenario::num_jobs`] threads.
+///
+/// Panics are caught per experiment, and each experiment is executed in its own `tracing` span.
+/// If [`Scenario::experiment_timeout`] is set, the time limit of an experiment starts
+/// when it is picked up by a thread, and experiments exceeding it are reported as timed out.
+/// Note that single experiments are not abandoned, i.e. the target runner should poll
+/// [`Experiment::is_cancelled`] and return early. Only the whole batch is abandoned
+/// if it takes longer than executing all experiments at their time limit would,
+/// so a hanging experiment delays the race by at most this long.
+#[derive(Debug, Clone)]
+pub struct ParallelTargetRunner<R>(R);
+
+impl<R> ParallelTargetRunner<R> {
+    /// Constructs a new `ParallelTargetRunner` executing the `target_runner`.
+    pub fn new(target_runner: R) -> Self {
+        Self(target_runner)
+    }
+}
+
+impl<I: Instance, R: TargetRunner<I>> BatchTargetRunner<I> for ParallelTargetRunner<R> {
+    fn run_batch(
+        &self,
+        scenario: &Scenario,
+        experiments: Vec<Experiment<I>>,
+    ) -> Vec<ExecResult<TargetResult>> {
+        let parent = Span::current();
+        parallel_map(experiments, scenario.num_jobs, |mut experiment| {
+            let span = experiment_span(
+                &parent,
+                &experiment.id,
+                experiment.instance_id.as_deref(),
+                experiment.seed,
+            );
+            let _entered = span.enter();
+
+            let start = Instant::now();
+            let timeout = scenario.experiment_timeout;
+            if let (Some(timeout), None) = (timeout, experiment.cancellation.deadline()) {
+                experiment.cancellation = CancellationToken::with_deadline(start + timeout);
+            }
+
+            let cancellation = experiment.cancellation.clone();
+            let result = run_catching_panics(&self.0, scenario, experiment);
+            match timeout {
+                Some(timeout) if cancellation.is_cancelled() => {
+                    Err(ExperimentTimeout { timeout }.into())
+                }
+                _ => result.map(|mut result| {
+                    result.time.get_or_insert_with(|| start.elapsed());
+                    result
+                }),
+            }
+        })
+    }
+
+    fn on_tuning_start(&self, scenario: &Scenario) -> eyre::Result<()> {
+        self.0.on_tuning_start(scenario)
+    }
+
+    fn on_tuning_end(&self, scenario: &Scenario) -> eyre::Result<()> {
+        self.0.on_tuning_end(scenario)
+    }
+
+    fn on_iteration_start(&self, scenario: &Scenario, iteration: u32) -> eyre::Result<()> {
+        self.0.on_iteration_start(scenario, iteration)
+    }
+
+    fn on_iteration_end(
+        &self,
+        scenario: &Scenario,
+        iteration: u32,
+        elites: &[String],
+    ) -> eyre::Result<()> {
+        self.0.on_iteration_end(scenario, iteration, elites)
+    }
+
+    fn on_new_instance(&self, scenario: &Scenario, instance: &I) -> eyre::Result<()> {
+        self.0.on_new_instance(scenario, instance)
+    }
+}
+
+/// Applies `f` to all `items` on a pool of `num_jobs` threads, preserving the order of the items.
+///
+/// Panics inside `f` are propagated after all threads finished.
+pub(crate) fn parallel_map<T, R, F>(items: Vec<T>, num_jobs: usize, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let num_items = items.len();
+    let num_jobs = num_jobs.clamp(1, num_items.max(1));
+    if num_jobs == 1 {
+        return items.into_iter().map(f).collect();
+    }
+
+    let queue = Mutex::new(items.into_iter().enumerate());
+    let mut results: Vec<Option<R>> = (0..num_items).map(|_| None).collect();
+
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..num_jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut processed = Vec::new();
+                    loop {
+                        let next = queue.lock().unwrap().next();
+                        let Some((index, item)) = next else {
+                            break;
+                        };
+                        processed.push((index, f(item)));
+                    }
+                    processed
+                })
+            })
+            .collect();
+
+        for worker in workers {
+            let processed = worker
+                .join()
+                .unwrap_or_else(|payload| std::panic::resume_unwind(payload));
+            for (index, result) in processed {
+                results[index] = Some(result);
+            }
+        }
+    });
+
+    results
+        .into_iter()
+        .map(|result| result.expect("every item is processed by a worker"))
+        .collect()
+}
+
+/// Wrapper to implement [`ErasedTargetRunner`] on.
+pub(crate) struct BatchTargetRunnerWrapper<I: Instance>(pub Box<dyn BatchTargetRunner<I>>);
+
+impl<I: Instance> BatchTargetRunnerWrapper<I> {
+    /// Executes the batch target runner on the `experiments`.
+    ///
+    /// A panic inside the target runner is returned as [`RunnerPanic`] error for every experiment.
+    fn run_experiments(
+        &self,
+        scenario: &Scenario,
+        experiments: Vec<Experiment<I>>,
+    ) -> Vec<ExecResult<TargetResult>> {
+        let configurations: Vec<_> = experiments
+            .iter()
+            .map(|experiment| (experiment.id.clone(), format!("{:?}", experiment.params)))
+            .collect();
+        let num_experiments = experiments.len();
+
+        match std::panic::catch_unwind(AssertUnwindSafe(|| self.0.run_batch(scenario, experiments)))
+        {
+            Ok(results) if results.len() == num_experiments => results,
+            Ok(results) => {
+                let num_results = results.len();
+                (0..num_experiments)
+                    .map(|_| {
+                        Err(eyre!(
+                            "batch target runner returned {num_results} results for {num_experiments} experiments"
+                        ))
+                    })
+                    .collect()
+            }
+            Err(payload) => {
+                let message = RunnerPanic::message(payload.as_ref());
+                configurations
+                    .into_iter()
+                    .map(|(configuration_id, params)| {
+                        Err(RunnerPanic {
+                            configuration_id,
+                            params,
+                            message: message.clone(),
+                        }
+                        .into())
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+impl<I: Instance> ErasedTargetRunner for BatchTargetRunnerWrapper<I> {
+    fn run(
+        &self,
+        scenario: &Scenario,
+        instances: &dyn ErasedInstances,
+        data: &ExperimentData,
+        param_space: &ParamSpace,
+        cancellation: CancellationToken,
+    ) -> ExecResult<TargetResult> {
+        let instance = load_instance::<I>(data, instances)?;
+        let experiment = Experiment::from_data(data, instance.as_ref(), param_space, cancellation);
+        self.run_experiments(scenario, vec![experiment])
+            .pop()
+            .expect("the number of results is checked")
+    }
+
+    fn run_batch(
+        &self,
+        scenario: &Scenario,
+        instances: &dyn ErasedInstances,
+        data: &[ExperimentData],
+        param_space: &ParamSpace,
+    ) -> Vec<ExecResult<TargetResult>> {
+        // The instances are loaded first, as the experiments borrow them.
+        // Experiments whose instance can't be loaded fail individually.
+        let mut results = Vec::with_capacity(data.len());
+        let mut loaded = Vec::with_capacity(data.len());
+        for data in data {
+            match load_instance::<I>(data, instances) {
+                Ok(instance) => {
+                    loaded.push((data, instance));
+                    results.push(None);
+                }
+                Err(e) => results.push(Some(Err(e))),
+            }
+        }
+
+        let experiments = loaded
+            .iter()
+            .map(|(data, instance)| {
+                Experiment::from_data(
+                    data,
+                    instance.as_ref(),
+                    param_space,
+                    CancellationToken::new(),
+                )
+            })
+            .collect();
+
+        let mut batch_results = self.run_experiments(scenario, experiments).into_iter();
+        results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| {
+                    batch_results
+                        .next()
+                        .expect("the number of results is checked")
+                })
+            })
+            .collect()
+    }
+
+    fn is_batch(&self) -> bool {
+        true
+    }
+
+    fn on_tuning_start(&self, scenario: &Scenario) -> eyre::Result<()> {
+        catch_panics("on_tuning_start", || self.0.on_tuning_start(scenario))
+    }
+
+    fn on_tuning_end(&self, scenario: &Scenario) -> eyre::Result<()> {
+        catch_panics("on_tuning_end", || self.0.on_tuning_end(scenario))
+    }
+
+    fn on_iteration_start(&self, scenario: &Scenario, iteration: u32) -> eyre::Result<()> {
+        catch_panics("on_iteration_start", || {
+            self.0.on_iteration_start(scenario, iteration)
+        })
+    }
+
+    fn on_iteration_end(
+        &self,
+        scenario: &Scenario,
+        iteration: u32,
+        elites: &[String],
+    ) -> eyre::Result<()> {
+        catch_panics("on_iteration_end", || {
+            self.0.on_iteration_end(scenario, iteration, elites)
+        })
+    }
+
+    fn on_new_instance(
+        &self,
+        scenario: &Scenario,
+        instances: &dyn ErasedInstances,
+        index: usize,
+    ) -> eyre::Result<()> {
+        let instance = load_instance_at::<I>(index, instances)?;
+        catch_panics("on_new_instance", || {
+            self.0.on_new_instance(scenario, instance.as_ref())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    use super::*;
+
+    #[test]
+    fn parallel_map_preserves_order() {
+        let items: Vec<u32> = (0..100).collect();
+        for num_jobs in [0, 1, 4, 200] {
+            let results = parallel_map(items.clone(), num_jobs, |item| item * 2);
+            assert_eq!(
+                results,
+                items.iter().map(|item| item * 2).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn parallel_map_handles_empty_input() {
+        let results = parallel_map(Vec::<u32>::new(), 4, |item| item);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn parallel_map_uses_at_most_num_jobs_threads() {
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+        parallel_map((0..16).collect(), 3, |_: u32| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(5));
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+        assert!(max_running.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn hook_panics_are_returned_as_errors() {
+        struct PanickingHooks;
+
+        impl BatchTargetRunner<u32> for PanickingHooks {
+            fn run_batch(
+                &self,
+                _scenario: &Scenario,
+                experiments: Vec<Experiment<u32>>,
+            ) -> Vec<ExecResult<TargetResult>> {
+                experiments.iter().map(|_| Ok(0.0.into())).collect()
+            }
+
+            fn on_tuning_start(&self, _scenario: &Scenario) -> eyre::Result<()> {
+                panic!("broken setup");
+            }
+        }
+
+        let runner = BatchTargetRunnerWrapper::<u32>(Box::new(PanickingHooks));
+        let error = runner
+            .on_tuning_start(&Scenario::builder().build())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "on_tuning_start panicked: broken setup");
+    }
+
+    #[test]
+    #[should_panic(expected = "item 7")]
+    fn parallel_map_propagates_panics() {
+        parallel_map((0..10).collect(), 4, |item: u32| {
+            if item == 7 {
+                panic!("item {item}");
+            }
+        });
+    }
+}
@@ -0,0 +1,239 @@
+//! Calling the lifecycle hooks of target runners.
+
+use std::{collections::HashSet, sync::Mutex};
+
+use indexmap::IndexMap;
+
+use crate::{
+    experiment::ExperimentData,
+    observer::{Event, Observers},
+    result::TargetResult,
+    runner::ErasedTargetRunner,
+    scenario::Scenario,
+    source::ErasedInstances,
+};
+
+/// The results of a configuration in the current iteration.
+#[derive(Default)]
+struct IterationResults {
+    /// The number of instances the configuration has been evaluated on, including previous iterations.
+    num_evaluated: u32,
+    num_results: u32,
+    total_cost: f64,
+}
+
+impl IterationResults {
+    fn mean_cost(&self) -> f64 {
+        self.total_cost / self.num_results as f64
+    }
+}
+
+#[derive(Default)]
+struct LifecycleState {
+    iteration: Option<u32>,
+    /// The results of each configuration id in the current iteration.
+    results: IndexMap<String, IterationResults>,
+    /// If `irace` passed the number of evaluated instances of all experiments
+    /// of the current iteration, which is required to determine the elites.
+    num_evaluated_known: bool,
+    /// The ids of the elites of the previous iteration.
+    elites: HashSet<String>,
+    /// The indices of all instances experiments were executed on.
+    instances: HashSet<usize>,
+}
+
+impl LifecycleState {
+    /// Returns the ids of the configurations which were evaluated on the most instances,
+    /// ordered by their mean cost in the current iteration, or `None` if the number of
+    /// evaluated instances is unknown.
+    ///
+    /// This approximates the elites of `irace`, which ranks the surviving configurations
+    /// by the statistical test of the scenario over all instances instead.
+    fn elites(&self) -> Option<Vec<String>> {
+        if !self.num_evaluated_known {
+            return None;
+        }
+
+        let max_evaluated = self
+            .results
+            .values()
+            .map(|results| results.num_evaluated)
+            .max()
+            .unwrap_or_default();
+
+        let mut elites: Vec<_> = self
+            .results
+            .iter()
+            .filter(|(_, results)| results.num_evaluated == max_evaluated)
+            .collect();
+        elites.sort_by(|(_, a), (_, b)| a.mean_cost().total_cmp(&b.mean_cost()));
+        Some(elites.into_iter().map(|(id, _)| id.clone()).collect())
+    }
+}
+
+/// Tracks the iterations and instances of a tuning to call the lifecycle hooks of a target runner
+/// and notify the observers.
+///
+/// `irace` does not report the progress of the tuning directly, so a new iteration is detected
+/// by the first experiment passing its [`RaceContext::iteration`].
+/// As `irace` waits for all experiments of a race step, experiments of different iterations
+/// never run concurrently.
+/// The elites of an iteration are derived from the [`RaceContext::num_evaluated`] of the
+/// experiments, and are therefore only known if `irace` passes it.
+///
+/// [`RaceContext::iteration`]: crate::RaceContext::iteration
+/// [`RaceContext::num_evaluated`]: crate::RaceContext::num_evaluated
+#[derive(Default)]
+pub(crate) struct Lifecycle {
+    state: Mutex<LifecycleState>,
+    observers: Observers,
+}
+
+impl Lifecycle {
+    /// Constructs a new `Lifecycle` notifying the `observers`.
+    pub fn new(observers: Observers) -> Self {
+        Self {
+            state: Default::default(),
+            observers,
+        }
+    }
+
+    /// Returns the observers to notify.
+    pub fn observers(&self) -> &Observers {
+        &self.observers
+    }
+
+    /// Ends the `iteration`, notifying the observers of the eliminated configurations and elites
+    /// before calling the hook.
+    ///
+    /// If the elites are unknown, only the end of the iteration is reported to the observers,
+    /// and the hook is not called.
+    fn end_iteration(
+        &self,
+        state: &mut LifecycleState,
+        runner: &dyn ErasedTargetRunner,
+        scenario: &Scenario,
+        iteration: u32,
+    ) -> eyre::Result<()> {
+        let elites = state.elites();
+        if let Some(elites) = &elites {
+            for configuration_id in state.results.keys() {
+                if !elites.contains(configuration_id) {
+                    self.observers.notify(Event::ConfigurationEliminated {
+                        configuration_id: configuration_id.clone(),
+                        iteration,
+                    });
+                }
+            }
+            for configuration_id in elites {
+                if !state.elites.contains(configuration_id) {
+                    self.observers.notify(Event::NewElite {
+                        configuration_id: configuration_id.clone(),
+                        iteration,
+                    });
+                }
+            }
+        }
+        self.observers.notify(Event::IterationFinished {
+            iteration,
+            elites: elites.clone(),
+        });
+
+        state.results.clear();
+        let Some(elites) = elites else {
+            state.elites.clear();
+            return Ok(());
+        };
+        state.elites = elites.iter().cloned().collect();
+        runner.on_iteration_end(scenario, iteration, &elites)
+    }
+
+    /// Calls the iteration hooks before the `experiments` are executed.
+    ///
+    /// The state is locked while calling the hooks, i.e. concurrent experiments wait
+    /// for the hooks to finish. Errors of the hooks abort the tuning.
+    pub fn before_experiments(
+        &self,
+        runner: &dyn ErasedTargetRunner,
+        scenario: &Scenario,
+        experiments: &[ExperimentData],
+    ) -> eyre::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        for data in experiments {
+            if let Some(iteration) = data.context.iteration {
+                if state.iteration != Some(iteration) {
+                    if let Some(previous) = state.iteration {
+                        self.end_iteration(&mut state, runner, scenario, previous)?;
+                    }
+                    state.iteration = Some(iteration);
+                    state.num_evaluated_known = true;
+                    runner.on_iteration_start(scenario, iteration)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls the instance hook before the first experiment on the instance of `data`.
+    ///
+    /// Errors of the hook are errors of the experiment, i.e. they are subject to the
+    /// failure policy of the scenario, and the hook is called again before the next
+    /// experiment on the instance.
+    /// The state is locked while calling the hook, like in [`before_experiments`].
+    ///
+    /// [`before_experiments`]: Self::before_experiments
+    pub fn prepare_instance(
+        &self,
+        runner: &dyn ErasedTargetRunner,
+        scenario: &Scenario,
+        instances: &dyn ErasedInstances,
+        data: &ExperimentData,
+    ) -> eyre::Result<()> {
+        let Some(index) = data.instance_index else {
+            return Ok(());
+        };
+
+        let mut state = self.state.lock().unwrap();
+        if state.instances.contains(&index) {
+            return Ok(());
+        }
+        runner.on_new_instance(scenario, instances, index)?;
+        state.instances.insert(index);
+        Ok(())
+    }
+
+    /// Records the `result` of an experiment to determine the elites of the iteration.
+    pub fn record_result(&self, data: &ExperimentData, result: &TargetResult) {
+        let mut state = self.state.lock().unwrap();
+        if state.iteration.is_none() || data.context.iteration != state.iteration {
+            return;
+        }
+
+        let Some(num_evaluated) = data.context.num_evaluated else {
+            state.num_evaluated_known = false;
+            return;
+        };
+        let results = state.results.entry(data.id.clone()).or_default();
+        results.num_results += 1;
+        results.total_cost += result.cost;
+        results.num_evaluated = results.num_evaluated.max(num_evaluated + 1);
+    }
+
+    /// Calls the hooks at the end of the tuning, ending the current iteration first.
+    ///
+    /// If both hooks fail, the error of the iteration hook is returned.
+    pub fn end_tuning(
+        &self,
+        runner: &dyn ErasedTargetRunner,
+        scenario: &Scenario,
+    ) -> eyre::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        let iteration_end = match state.iteration.take() {
+            Some(iteration) => self.end_iteration(&mut state, runner, scenario, iteration),
+            None => Ok(()),
+        };
+        // The tuning is ended even if ending the iteration failed.
+        let tuning_end = runner.on_tuning_end(scenario);
+        iteration_end.and(tuning_end)
+    }
+}
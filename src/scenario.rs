@@ -1,13 +1,23 @@
 //! Configuring `irace`.
 
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
+use eyre::{bail, ensure};
+use indexmap::IndexMap;
 use pyo3::{
     types::{PyDict, PyModule},
     PyObject, PyResult, Python, ToPyObject,
 };
 use typed_builder::TypedBuilder;
 
+use crate::param_space::ParamSpace;
+
+mod budget;
+mod file;
+
+pub use budget::BudgetEstimate;
+pub use file::{ParsedScenario, UnsupportedKey};
+
 /// The stdout verbosity of `irace`.
 #[derive(Debug, Copy, Clone)]
 pub enum Verbosity {
@@ -21,12 +31,208 @@ pub enum Verbosity {
     Debug = 3,
 }
 
+impl Verbosity {
+    /// Converts an `irace` debug level into a `Verbosity`.
+    ///
+    /// Levels above 3 are mapped to [`Verbosity::Debug`].
+    pub fn from_level(level: u32) -> Self {
+        match level {
+            0 => Verbosity::Silent,
+            1 => Verbosity::Minimal,
+            2 => Verbosity::Standard,
+            _ => Verbosity::Debug,
+        }
+    }
+}
+
+/// Specifies where the console output of `irace` and R is written.
+///
+/// The output is captured by replacing the Python `sys.stdout` and `sys.stderr` while `irace`
+/// is running, i.e. output written directly to the file descriptors, e.g. by native code,
+/// is not captured.
+/// As the streams are shared by all runs of [`multi_irace`](crate::multi_irace),
+/// it takes the setting for all runs as parameter instead.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ConsoleOutput {
+    /// Print to the console of the process.
+    #[default]
+    Inherit,
+    /// Forward the output line by line as `tracing` events with target `irace`.
+    ///
+    /// Output on stdout is logged at info level, output on stderr at warn level,
+    /// or at error level for R error messages.
+    Log,
+    /// Write the output to the file at the path, which is overwritten.
+    File(PathBuf),
+}
+
+/// The statistical test used by `irace` to eliminate configurations.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TestType {
+    /// Friedman test with post-hoc comparisons.
+    FTest,
+    /// Paired Student's t-test without correction for multiple comparisons.
+    TTest,
+    /// Paired Student's t-test with Bonferroni correction.
+    TTestBonferroni,
+    /// Paired Student's t-test with Holm correction.
+    TTestHolm,
+}
+
+impl TestType {
+    /// Returns the name of the test used by `irace`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TestType::FTest => "F-test",
+            TestType::TTest => "t-test",
+            TestType::TTestBonferroni => "t-test-bonferroni",
+            TestType::TTestHolm => "t-test-holm",
+        }
+    }
+
+    /// Converts the name of a test used by `irace` into a `TestType`.
+    ///
+    /// The aliases accepted by `irace`, e.g. `friedman` or `t.holm`, are supported as well.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "F-test" | "friedman" => Some(TestType::FTest),
+            "t-test" | "t.none" => Some(TestType::TTest),
+            "t-test-bonferroni" | "t.bonferroni" => Some(TestType::TTestBonferroni),
+            "t-test-holm" | "t.holm" => Some(TestType::TTestHolm),
+            _ => None,
+        }
+    }
+}
+
+/// The cost reported to `irace` for failed experiments.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Penalty {
+    /// A fixed penalty cost.
+    Fixed(f64),
+    /// The worst cost seen on the same instance, worsened by `factor`.
+    ///
+    /// Requires [`Scenario::num_jobs`] = 1 or a batch run, as the worst costs are not
+    /// shared between the processes `irace` forks to execute experiments otherwise.
+    ///
+    /// For a worst cost `w`, the penalty is `w + (factor - 1) * |w|`, i.e. `w * factor` for
+    /// positive costs.
+    /// If no cost was recorded on the instance yet, the `fallback` cost is used.
+    WorstOnInstance { factor: f64, fallback: f64 },
+}
+
+impl Penalty {
+    /// Returns the penalty cost given the `worst` cost seen on the instance.
+    pub fn cost(&self, worst: Option<f64>) -> f64 {
+        match *self {
+            Penalty::Fixed(cost) => cost,
+            Penalty::WorstOnInstance { factor, fallback } => worst
+                .map(|worst| worst + (factor - 1.0) * worst.abs())
+                .unwrap_or(fallback),
+        }
+    }
+}
+
+/// The handling of panics inside the [`TargetRunner`].
+///
+/// [`TargetRunner`]: crate::TargetRunner
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PanicPolicy {
+    /// Abort the tuning, reporting the panic message and the offending configuration.
+    Abort,
+    /// Record the experiment as failed with the given penalty cost, and continue the tuning.
+    Penalty(Penalty),
+}
+
+/// The result reported for experiments which exceed their time limit.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TimeoutPolicy {
+    /// Report the time limit (in seconds) as cost, i.e. the censored running time.
+    ///
+    /// This is appropriate if the cost is the running time of the target algorithm.
+    Censored,
+    /// Report the given penalty cost.
+    Penalty(Penalty),
+}
+
+/// The handling of errors returned by the [`TargetRunner`].
+///
+/// A failed experiment is first retried [`retries`] times with the same seed.
+/// If it still fails, the [`penalty`] cost is reported to `irace`,
+/// or the tuning is aborted if no penalty is specified.
+///
+/// [`TargetRunner`]: crate::TargetRunner
+/// [`retries`]: Self::retries
+/// [`penalty`]: Self::penalty
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct FailurePolicy {
+    /// The number of times a failed experiment is retried.
+    pub retries: u32,
+    /// The penalty cost for experiments which still fail after all retries.
+    pub penalty: Option<Penalty>,
+}
+
+impl FailurePolicy {
+    /// Aborts the tuning on the first error.
+    pub fn abort() -> Self {
+        Self::default()
+    }
+
+    /// Retries failed experiments `retries` times, and aborts the tuning if they still fail.
+    pub fn retry(retries: u32) -> Self {
+        Self {
+            retries,
+            penalty: None,
+        }
+    }
+
+    /// Reports the `penalty` cost for failed experiments.
+    pub fn penalty(penalty: Penalty) -> Self {
+        Self {
+            retries: 0,
+            penalty: Some(penalty),
+        }
+    }
+
+    /// Sets the number of `retries` before the policy applies.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+}
+
 /// A tuning scenario.
 ///
 /// The scenario bundles important parameters and flags for `irace`.
 ///
 /// Currently, only a small percentage of the parameters available
 /// to the `irace` R package are supported.
+///
+/// Scenarios can be read from and written to `irace` scenario files,
+/// see [`from_scenario_file`] and [`write_scenario_file`].
+///
+/// [`from_scenario_file`]: Self::from_scenario_file
+/// [`write_scenario_file`]: Self::write_scenario_file
+///
+/// # Presets
+///
+/// For common tuning shapes, the presets [`quick`], [`standard`] and [`thorough`] derive
+/// the budget and race settings from the parameter space and number of instances.
+/// Individual fields can be overridden using [`preset`], which derives the budget
+/// from the overridden settings:
+///
+/// ```ignore
+/// let scenario = Scenario::preset(Preset::Standard, &param_space, instances.len())
+///     .with(|scenario| {
+///         scenario.instance_repetitions = 3;
+///         scenario.num_jobs = 4;
+///     })
+///     .build();
+/// ```
+///
+/// [`quick`]: Self::quick
+/// [`standard`]: Self::standard
+/// [`thorough`]: Self::thorough
+/// [`preset`]: Self::preset
 #[derive(Debug, Clone, TypedBuilder)]
 pub struct Scenario {
     /// The upper bound of experiments to perform (tuning budget).
@@ -35,12 +241,46 @@ pub struct Scenario {
     /// The lower bound of experiments to perform (tuning budget).
     #[builder(default = None, setter(strip_option))]
     pub min_experiments: Option<u32>,
+    /// The number of instance blocks each configuration is evaluated on before the first
+    /// elimination test, see [`block_size`](Self::block_size).
+    ///
+    /// Must be a multiple of [`each_test`](Self::each_test).
+    #[builder(default = 5)]
+    pub first_test: u32,
+    /// The number of instance blocks each configuration is evaluated on between elimination tests.
+    #[builder(default = 1)]
+    pub each_test: u32,
+    /// The statistical test used to eliminate configurations.
+    #[builder(default = TestType::FTest)]
+    pub test_type: TestType,
+    /// The confidence level of the elimination test.
+    #[builder(default = 0.95)]
+    pub confidence: f64,
     /// Specifies if elitist `irace` should be used.
     #[builder(default = true)]
     pub elitist: bool,
     /// Specifies if the target algorithm is deterministic (`true`) or stochastic (`false`).
     #[builder(default = false)]
     pub deterministic: bool,
+    /// Specifies if the order of the training instances is shuffled (`true`) or kept as given (`false`).
+    ///
+    /// Disabling this allows to deliberately order instances, e.g. putting easy instances first.
+    #[builder(default = true)]
+    pub sample_instances: bool,
+    /// The number of times each instance is repeated in the list of training instances.
+    ///
+    /// `irace` assigns a different seed to each repetition, i.e. each instance is
+    /// evaluated with `instance_repetitions` different seeds.
+    /// Repetitions of the same instance are placed next to each other.
+    #[builder(default = 1)]
+    pub instance_repetitions: usize,
+    /// The number of training instances that make up a block.
+    ///
+    /// Configurations are only eliminated after evaluating a complete block, i.e.
+    /// [`first_test`](Self::first_test) and [`each_test`](Self::each_test) count blocks.
+    /// The number of training instances (including repetitions) must be a multiple of the block size.
+    #[builder(default = 1)]
+    pub block_size: usize,
     /// The path of the log file (by default `irace.Rdata`).
     #[builder(default = None, setter(into, strip_option))]
     pub log_file: Option<PathBuf>,
@@ -50,6 +290,11 @@ pub struct Scenario {
     /// The number of experiments to perform in parallel.
     ///
     /// Note that parallelism on Windows is currently not supported, and a value > 1 will abort.
+    ///
+    /// For runs with a [`BatchTargetRunner`](crate::BatchTargetRunner), the experiments are
+    /// not parallelized by `irace`, but by the target runner, e.g. a [`ParallelTargetRunner`].
+    ///
+    /// [`ParallelTargetRunner`]: crate::ParallelTargetRunner
     #[builder(default = 1)]
     pub num_jobs: usize,
     /// The initial RNG seed.
@@ -58,24 +303,336 @@ pub struct Scenario {
     /// The verbosity of the stdout output of `irace`.
     #[builder(default = Verbosity::Silent)]
     pub verbose: Verbosity,
+    /// Specifies where the console output of `irace` is written.
+    ///
+    /// Ignored by [`multi_irace`](crate::multi_irace), which takes the setting as parameter.
+    #[builder(default)]
+    pub console: ConsoleOutput,
+    /// Specifies how panics inside the target runner are handled.
+    #[builder(default = PanicPolicy::Abort)]
+    pub on_panic: PanicPolicy,
+    /// Specifies how errors returned by the target runner are handled.
+    #[builder(default)]
+    pub on_error: FailurePolicy,
+    /// The wall-clock time limit of a single experiment.
+    ///
+    /// If the time limit expires, the experiment is cancelled and reported according to
+    /// [`on_timeout`](Self::on_timeout), without waiting for the target runner to return.
+    #[builder(default = None, setter(strip_option))]
+    pub experiment_timeout: Option<Duration>,
+    /// Specifies the result reported for experiments which exceed their time limit.
+    ///
+    /// Required if an [`experiment_timeout`](Self::experiment_timeout) is set, since
+    /// the censored running time is only a meaningful cost if the cost is the running time.
+    #[builder(default = None, setter(strip_option))]
+    pub on_timeout: Option<TimeoutPolicy>,
+}
+
+/// A named tuning shape, see [`Scenario::preset`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Preset {
+    /// A quick smoke tuning, see [`Scenario::quick`].
+    Quick,
+    /// A standard tuning, see [`Scenario::standard`].
+    Standard,
+    /// A thorough tuning, see [`Scenario::thorough`].
+    Thorough,
+}
+
+/// Builder of a [`Scenario`] from a [`Preset`], constructed using [`Scenario::preset`].
+///
+/// Fields overridden using [`with`](Self::with) are kept, and the budget is derived
+/// from the resulting scenario when it is [built](Self::build).
+#[derive(Clone)]
+pub struct PresetBuilder<'a> {
+    preset: Preset,
+    param_space: &'a ParamSpace,
+    num_instances: usize,
+    scenario: Scenario,
+}
+
+impl PresetBuilder<'_> {
+    /// Overrides fields of the scenario, e.g. `first_test` or `num_jobs`.
+    ///
+    /// If a budget is set, i.e. `max_experiments` or `min_experiments`, it is used
+    /// instead of the budget of the preset.
+    pub fn with(mut self, f: impl FnOnce(&mut Scenario)) -> Self {
+        f(&mut self.scenario);
+        self
+    }
+
+    /// Builds the scenario, deriving the budget of the preset unless it was overridden.
+    ///
+    /// The budget is at least the [`minimum_budget`](Scenario::minimum_budget).
+    pub fn build(self) -> Scenario {
+        let mut scenario = self.scenario;
+        if scenario.budget().is_none() {
+            let budget =
+                BudgetEstimate::suggested_budget(&scenario, self.param_space, self.num_instances);
+            let budget = match self.preset {
+                Preset::Quick => budget / 2,
+                Preset::Standard => budget,
+                Preset::Thorough => budget * 4,
+            };
+            scenario.max_experiments = Some(budget.max(scenario.minimum_budget(self.param_space)));
+        }
+        scenario
+    }
+}
+
+/// The default number of `irace` iterations and minimum number of surviving configurations
+/// for a parameter space with `num_params` parameters, i.e. `floor(2 + log2(num_params))`.
+pub(crate) fn default_num_iterations(num_params: usize) -> u32 {
+    (2.0 + (num_params.max(1) as f64).log2()).floor() as u32
 }
 
 impl Scenario {
+    /// Returns a builder for the `preset`, which allows to override fields before the budget
+    /// is derived, see [`PresetBuilder`].
+    pub fn preset(
+        preset: Preset,
+        param_space: &ParamSpace,
+        num_instances: usize,
+    ) -> PresetBuilder<'_> {
+        let scenario = match preset {
+            Preset::Quick => Self::builder()
+                .first_test(Self::preset_first_test(5, num_instances))
+                .elitist(false)
+                .build(),
+            Preset::Standard => Self::builder()
+                .first_test(Self::preset_first_test(5, num_instances))
+                .build(),
+            // `irace` only runs its testing phase on separate test instances, which presets
+            // do not know of, so "thorough" refers to the elimination test instead.
+            Preset::Thorough => Self::builder()
+                .first_test(Self::preset_first_test(10, num_instances))
+                .test_type(TestType::TTestHolm)
+                .confidence(0.99)
+                .build(),
+        };
+
+        PresetBuilder {
+            preset,
+            param_space,
+            num_instances,
+            scenario,
+        }
+    }
+
+    /// Returns a preset for a quick smoke tuning.
+    ///
+    /// The budget is half of the [`standard`] budget, but at least the [`minimum_budget`].
+    /// Elitism is disabled to avoid re-evaluating elite configurations.
+    ///
+    /// [`standard`]: Self::standard
+    /// [`minimum_budget`]: Self::minimum_budget
+    pub fn quick(param_space: &ParamSpace, num_instances: usize) -> Self {
+        Self::preset(Preset::Quick, param_space, num_instances).build()
+    }
+
+    /// Returns a preset for a standard tuning.
+    ///
+    /// The budget allows `irace` to perform its default number of iterations with reasonably
+    /// large races, and to evaluate the surviving configurations on all instances.
+    pub fn standard(param_space: &ParamSpace, num_instances: usize) -> Self {
+        Self::preset(Preset::Standard, param_space, num_instances).build()
+    }
+
+    /// Returns a preset for a thorough tuning.
+    ///
+    /// Compared to [`standard`], the budget is four times larger, configurations are evaluated
+    /// on more instances before the first elimination test, and the t-test with
+    /// Holm correction at a confidence level of 0.99 is used to eliminate configurations.
+    ///
+    /// The stricter testing refers to this elimination test: the testing phase of `irace`,
+    /// i.e. evaluating the elite configurations on separate test instances, is not enabled.
+    ///
+    /// [`standard`]: Self::standard
+    pub fn thorough(param_space: &ParamSpace, num_instances: usize) -> Self {
+        Self::preset(Preset::Thorough, param_space, num_instances).build()
+    }
+
+    /// Limits the preferred `first_test` of a preset to the number of instances.
+    fn preset_first_test(first_test: u32, num_instances: usize) -> u32 {
+        first_test.min(num_instances.max(1) as u32)
+    }
+
+    /// Returns the number of instances `irace` evaluates new configurations on before
+    /// eliminating any of them, i.e. `max(5, first_test * block_size)`.
+    pub(crate) fn mu(&self) -> u32 {
+        (self.first_test * self.block_size as u32).max(5)
+    }
+
+    /// Returns the number of instances `irace` evaluates configurations on between
+    /// elimination tests, i.e. `each_test * block_size`.
+    pub(crate) fn each_test_instances(&self) -> u32 {
+        self.each_test * self.block_size as u32
+    }
+
+    /// Returns the tuning budget, i.e. the number of experiments `irace` performs at most.
+    ///
+    /// If only [`min_experiments`] is set, it is used as budget.
+    ///
+    /// [`min_experiments`]: Self::min_experiments
+    pub fn budget(&self) -> Option<u32> {
+        self.max_experiments.or(self.min_experiments)
+    }
+
+    /// Returns the minimum budget `irace` requires to tune the given `param_space`.
+    ///
+    /// This follows the rule of thumb `irace` uses to reject insufficient budgets:
+    /// With `N = floor(2 + log2(num_params))` iterations and at least `N + 1` configurations
+    /// in each race, each evaluated on `mu = max(5, first_test * block_size)` instances,
+    /// at least `(N + 1) * mu * N` experiments are required.
+    pub fn minimum_budget(&self, param_space: &ParamSpace) -> u32 {
+        let num_iterations = default_num_iterations(param_space.num_params());
+        let min_survival = num_iterations;
+        (min_survival + 1) * self.mu() * num_iterations
+    }
+
+    /// Checks the scenario for consistency with the `param_space` and number of instances.
+    ///
+    /// This catches misconfigurations which would otherwise only fail after `irace` started.
+    pub fn check(&self, param_space: &ParamSpace, num_instances: usize) -> eyre::Result<()> {
+        ensure!(self.num_jobs > 0, "`num_jobs` must be at least 1");
+
+        if let (Some(min), Some(max)) = (self.min_experiments, self.max_experiments) {
+            ensure!(
+                min <= max,
+                "`min_experiments` ({min}) is greater than `max_experiments` ({max})"
+            );
+        }
+
+        let Some(budget) = self.budget() else {
+            bail!(
+                "missing tuning budget: either `max_experiments` or `min_experiments` must be set"
+            );
+        };
+
+        if let Some(exec_dir) = &self.exec_dir {
+            ensure!(
+                exec_dir.is_dir(),
+                "`exec_dir` {} does not exist or is not a directory",
+                exec_dir.display()
+            );
+        }
+
+        if let Some(timeout) = self.experiment_timeout {
+            ensure!(!timeout.is_zero(), "`experiment_timeout` must be positive");
+            ensure!(
+                self.on_timeout.is_some(),
+                "`on_timeout` must be set if an `experiment_timeout` is set"
+            );
+        }
+
+        ensure!(num_instances > 0, "at least one instance is required");
+        ensure!(
+            self.each_test > 0 && self.first_test % self.each_test == 0,
+            "`first_test` ({}) must be a multiple of `each_test` ({})",
+            self.first_test,
+            self.each_test
+        );
+        ensure!(
+            self.confidence > 0.0 && self.confidence < 1.0,
+            "`confidence` ({}) must be in the open interval (0, 1)",
+            self.confidence
+        );
+        ensure!(
+            self.instance_repetitions > 0,
+            "`instance_repetitions` must be at least 1"
+        );
+        ensure!(self.block_size > 0, "`block_size` must be at least 1");
+        let num_training_instances = num_instances * self.instance_repetitions;
+        ensure!(
+            num_training_instances % self.block_size == 0,
+            "the number of training instances ({num_training_instances}) is not a multiple of `block_size` ({})",
+            self.block_size
+        );
+        ensure!(
+            param_space.num_params() > 0,
+            "the parameter space must contain at least one parameter"
+        );
+        ensure!(
+            !param_space.is_nested(),
+            "nested parameter spaces are not supported, consider using `ParamSpace::flatten`"
+        );
+
+        let minimum_budget = self.minimum_budget(param_space);
+        ensure!(
+            budget >= minimum_budget,
+            "insufficient tuning budget: {} parameters require at least {} experiments, but the budget is {}",
+            param_space.num_params(),
+            minimum_budget,
+            budget
+        );
+
+        Ok(())
+    }
+
+    /// Returns the list of training instance indices passed to `irace`.
+    ///
+    /// Each index is repeated [`instance_repetitions`] times.
+    ///
+    /// [`instance_repetitions`]: Self::instance_repetitions
+    pub(crate) fn training_instances(&self, num_instances: usize) -> Vec<usize> {
+        (0..num_instances)
+            .flat_map(|index| std::iter::repeat(index).take(self.instance_repetitions))
+            .collect()
+    }
+
+    /// Returns the list of training instance indices passed to `irace`, stratified by the
+    /// `classes` of the instances.
+    ///
+    /// The instances (including repetitions) of each class are interleaved, so each
+    /// consecutive run of as many instances as there are classes covers all classes,
+    /// as long as instances of each class remain.
+    /// Classes are ordered by their first occurrence, instances without class form their own class.
+    pub(crate) fn stratified_training_instances(&self, classes: &[Option<String>]) -> Vec<usize> {
+        let mut strata: IndexMap<Option<&str>, Vec<usize>> = IndexMap::new();
+        for (index, class) in classes.iter().enumerate() {
+            strata
+                .entry(class.as_deref())
+                .or_default()
+                .extend(std::iter::repeat(index).take(self.instance_repetitions));
+        }
+
+        let mut strata: Vec<_> = strata.into_values().map(Vec::into_iter).collect();
+        let num_training_instances = classes.len() * self.instance_repetitions;
+        let mut training_instances = Vec::with_capacity(num_training_instances);
+        while training_instances.len() < num_training_instances {
+            training_instances.extend(strata.iter_mut().filter_map(Iterator::next));
+        }
+        training_instances
+    }
+
     pub(crate) fn as_py_object(
         &self,
         py: Python,
-        num_instances: usize,
+        training_instances: Vec<usize>,
+        instance_names: Option<&[String]>,
         irace: &PyModule,
+        hooks: ScenarioHooks,
     ) -> PyResult<PyObject> {
         let kwargs = PyDict::new(py);
-        kwargs
-            .set_item("max_experiments", self.max_experiments)
-            .unwrap();
-        kwargs
-            .set_item("min_experiments", self.min_experiments)
-            .unwrap();
+        kwargs.set_item("max_experiments", self.max_experiments)?;
+        kwargs.set_item("min_experiments", self.min_experiments)?;
+        kwargs.set_item("first_test", self.first_test)?;
+        kwargs.set_item("each_test", self.each_test)?;
+        kwargs.set_item("test_type", self.test_type.name())?;
+        kwargs.set_item("confidence", self.confidence)?;
         kwargs.set_item("elitist", self.elitist)?;
-        kwargs.set_item("instances", (0..num_instances).collect::<Vec<_>>())?;
+        match instance_names {
+            Some(names) => {
+                let names: Vec<_> = training_instances
+                    .into_iter()
+                    .map(|index| names[index].as_str())
+                    .collect();
+                kwargs.set_item("instances", names)?;
+            }
+            None => kwargs.set_item("instances", training_instances)?,
+        }
+        kwargs.set_item("sample_instances", self.sample_instances)?;
+        kwargs.set_item("block_size", self.block_size)?;
         kwargs.set_item("deterministic", self.deterministic)?;
         kwargs.set_item(
             "log_file",
@@ -85,8 +642,16 @@ impl Scenario {
             "exec_dir",
             self.exec_dir.as_ref().map(|path| path.as_os_str().clone()),
         )?;
-        kwargs.set_item("deterministic", self.deterministic)?;
-        kwargs.set_item("n_jobs", self.num_jobs)?;
+        if let Some(target_evaluator) = hooks.target_evaluator {
+            kwargs.set_item("target_evaluator", target_evaluator)?;
+        }
+        if let Some(target_runner_parallel) = hooks.target_runner_parallel {
+            // Batches are executed by the target runner, which avoids the fork-based parallelism of R.
+            kwargs.set_item("target_runner_parallel", target_runner_parallel)?;
+            kwargs.set_item("n_jobs", 1)?;
+        } else {
+            kwargs.set_item("n_jobs", self.num_jobs)?;
+        }
         kwargs.set_item("seed", self.seed)?;
         kwargs.set_item("verbose", self.verbose as u32)?;
 
@@ -94,3 +659,99 @@ impl Scenario {
         Ok(scenario.to_object(py))
     }
 }
+
+/// Python callables passed to `irace` as part of the scenario.
+#[derive(Default)]
+pub(crate) struct ScenarioHooks {
+    /// Executes a batch of experiments at once (`targetRunnerParallel`).
+    pub target_runner_parallel: Option<PyObject>,
+    /// Computes the costs after all configurations were executed on an instance (`targetEvaluator`).
+    pub target_evaluator: Option<PyObject>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classes(classes: &[Option<&str>]) -> Vec<Option<String>> {
+        classes
+            .iter()
+            .map(|class| class.map(str::to_owned))
+            .collect()
+    }
+
+    #[test]
+    fn budget_counts_blocks() {
+        let mut param_space = ParamSpace::new();
+        param_space.add_real("x", 0.0, 1.0, false);
+        let scenario = Scenario::builder()
+            .first_test(2)
+            .block_size(4)
+            .max_experiments(1000)
+            .build();
+        assert_eq!(scenario.mu(), 8);
+        assert_eq!(scenario.each_test_instances(), 4);
+        assert_eq!(
+            scenario.minimum_budget(&param_space),
+            Scenario::builder()
+                .first_test(8)
+                .build()
+                .minimum_budget(&param_space)
+        );
+    }
+
+    #[test]
+    fn check_accepts_first_test_not_multiple_of_block_size() {
+        let mut param_space = ParamSpace::new();
+        param_space.add_real("x", 0.0, 1.0, false);
+        let scenario = Scenario::builder()
+            .first_test(5)
+            .block_size(2)
+            .max_experiments(1000)
+            .build();
+        assert!(scenario.check(&param_space, 10).is_ok());
+        assert!(scenario.check(&param_space, 9).is_err());
+    }
+
+    #[test]
+    fn stratified_training_instances_interleave_classes() {
+        let scenario = Scenario::builder().build();
+        let classes = classes(&[Some("a"), Some("a"), Some("b"), Some("b"), Some("c")]);
+        assert_eq!(
+            scenario.stratified_training_instances(&classes),
+            vec![0, 2, 4, 1, 3]
+        );
+    }
+
+    #[test]
+    fn stratified_training_instances_with_repetitions() {
+        let scenario = Scenario::builder().instance_repetitions(2).build();
+        let classes = classes(&[Some("a"), Some("b"), Some("a")]);
+        assert_eq!(
+            scenario.stratified_training_instances(&classes),
+            vec![0, 1, 0, 1, 2, 2]
+        );
+    }
+
+    #[test]
+    fn stratified_training_instances_group_instances_without_class() {
+        let scenario = Scenario::builder().build();
+        let classes = classes(&[None, Some("a"), None, Some("a")]);
+        assert_eq!(
+            scenario.stratified_training_instances(&classes),
+            vec![0, 1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn stratified_training_instances_contain_all_instances() {
+        let scenario = Scenario::builder().instance_repetitions(3).build();
+        let classes = classes(&[Some("a"), Some("b"), Some("b"), Some("b"), None]);
+        let mut training_instances = scenario.stratified_training_instances(&classes);
+        training_instances.sort_unstable();
+        assert_eq!(
+            training_instances,
+            scenario.training_instances(classes.len())
+        );
+    }
+}
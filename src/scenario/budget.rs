@@ -0,0 +1,264 @@
+//! Estimating the tuning budget of a scenario.
+
+use std::time::{Duration, Instant};
+
+use eyre::{ensure, WrapErr};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    experiment::{to_params, RaceContext},
+    param_space::ParamSpace,
+    runner::{catch_panics, run_catching_panics, Instance, TargetRunner},
+    scenario::{default_num_iterations, Scenario},
+    CancellationToken, Experiment,
+};
+
+/// The number of configurations raced per surviving configuration in each iteration
+/// when suggesting a budget without time limit.
+const CONFIGURATIONS_PER_SURVIVOR: u32 = 4;
+
+/// The number of iterations below which a budget is considered too small for `irace`
+/// to converge.
+const MIN_USEFUL_ITERATIONS: u32 = 3;
+
+/// An estimate of the tuning budget for a [`Scenario`].
+///
+/// Use the suggested [`max_experiments`] as budget of the scenario.
+///
+/// [`max_experiments`]: Self::max_experiments
+#[derive(Debug, Clone)]
+pub struct BudgetEstimate {
+    /// The suggested number of experiments (tuning budget).
+    pub max_experiments: u32,
+    /// The (mean) duration of a single experiment the estimate is based on.
+    pub experiment_time: Duration,
+    /// The expected wall-clock time of the tuning, using [`Scenario::num_jobs`] parallel jobs.
+    pub wall_time: Duration,
+    /// The number of iterations `irace` is expected to perform with the suggested budget.
+    pub num_iterations: u32,
+    /// Warnings about the suggested budget, e.g. if `irace` would only perform few iterations.
+    pub warnings: Vec<String>,
+}
+
+/// Simulates the budget allocation of `irace` and returns the number of iterations it
+/// would perform with a `budget` for `num_params` parameters.
+///
+/// In iteration `j`, `irace` uses an equal share `B_j` of the remaining budget to race
+/// `floor(B_j / (mu + each_test * block_size * min(5, j)))` configurations, and stops if this
+/// is not more than the minimum number of surviving configurations.
+fn expected_iterations(scenario: &Scenario, budget: u32, num_params: usize) -> u32 {
+    let num_iterations = default_num_iterations(num_params);
+    let min_survival = num_iterations;
+
+    let mut remaining = budget as f64;
+    let mut iterations = 0;
+    for j in 1..=num_iterations {
+        let iteration_budget = remaining / (num_iterations - j + 1) as f64;
+        let experiments_per_configuration =
+            scenario.mu() + scenario.each_test_instances() * j.min(5);
+        let num_configurations =
+            (iteration_budget / experiments_per_configuration as f64).floor() as u32;
+        if num_configurations <= min_survival {
+            break;
+        }
+        remaining -= iteration_budget;
+        iterations += 1;
+    }
+    iterations
+}
+
+impl BudgetEstimate {
+    /// Returns a budget which allows `irace` to perform its default number of iterations,
+    /// racing `CONFIGURATIONS_PER_SURVIVOR` times the minimum number of surviving configurations
+    /// in each iteration, and to evaluate the surviving configurations on all instances.
+    pub(crate) fn suggested_budget(
+        scenario: &Scenario,
+        param_space: &ParamSpace,
+        num_instances: usize,
+    ) -> u32 {
+        let num_iterations = default_num_iterations(param_space.num_params());
+        let min_survival = num_iterations;
+        let num_configurations = CONFIGURATIONS_PER_SURVIVOR * (min_survival + 1);
+        let races: u32 = (1..=num_iterations)
+            .map(|j| {
+                num_configurations * (scenario.mu() + scenario.each_test_instances() * j.min(5))
+            })
+            .sum();
+        let num_training_instances = (num_instances * scenario.instance_repetitions) as u32;
+        races.max(num_training_instances * (min_survival + 1))
+    }
+
+    /// Estimates the budget from the (mean) duration of a single experiment.
+    ///
+    /// If a `time_limit` is given, the suggested budget is the number of experiments that fit
+    /// into this wall-clock time using [`Scenario::num_jobs`] parallel jobs.
+    /// Otherwise, a budget that allows `irace` to perform its default number of iterations
+    /// with reasonably large races is suggested.
+    pub fn from_experiment_time(
+        scenario: &Scenario,
+        param_space: &ParamSpace,
+        num_instances: usize,
+        experiment_time: Duration,
+        time_limit: Option<Duration>,
+    ) -> Self {
+        let num_params = param_space.num_params();
+        let num_jobs = scenario.num_jobs.max(1) as u32;
+
+        let max_experiments = match time_limit {
+            Some(time_limit) if !experiment_time.is_zero() => {
+                let experiments =
+                    time_limit.as_secs_f64() * num_jobs as f64 / experiment_time.as_secs_f64();
+                experiments.floor().min(u32::MAX as f64) as u32
+            }
+            _ => Self::suggested_budget(scenario, param_space, num_instances),
+        };
+
+        let wall_time = experiment_time * max_experiments.div_ceil(num_jobs);
+        let num_iterations = expected_iterations(scenario, max_experiments, num_params);
+
+        let mut warnings = Vec::new();
+        let minimum_budget = scenario.minimum_budget(param_space);
+        if max_experiments < minimum_budget {
+            warnings.push(format!(
+                "the budget of {max_experiments} experiments is below the minimum of {minimum_budget} experiments required by `irace`"
+            ));
+        }
+        if num_iterations < MIN_USEFUL_ITERATIONS {
+            warnings.push(format!(
+                "`irace` would only perform {num_iterations} iteration(s) with a budget of {max_experiments} experiments"
+            ));
+        }
+        let num_training_instances = num_instances * scenario.instance_repetitions;
+        let first_test_instances = scenario.first_test as usize * scenario.block_size;
+        if scenario.deterministic && num_training_instances < first_test_instances {
+            warnings.push(format!(
+                "only {num_training_instances} training instance(s) for a deterministic target algorithm, but the first elimination test requires {first_test_instances}"
+            ));
+        }
+
+        Self {
+            max_experiments,
+            experiment_time,
+            wall_time,
+            num_iterations,
+            warnings,
+        }
+    }
+
+    /// Estimates the budget by executing the `target_runner` on `num_samples` random
+    /// configurations, cycling through the `instances`.
+    ///
+    /// The random configurations are sampled using the seed of the `scenario`, if any.
+    /// If the instances are named, their `instance_names` are passed to the target runner
+    /// like in a tuning, see [`Run::with_instance_names`].
+    /// The lifecycle hooks of the target runner are called like in a tuning, except for
+    /// the iteration hooks, and only the sample runs themselves are timed.
+    /// See [`from_experiment_time`] for how the budget is derived from the mean duration.
+    ///
+    /// [`from_experiment_time`]: Self::from_experiment_time
+    /// [`Run::with_instance_names`]: crate::Run::with_instance_names
+    pub fn from_sample_runs<I: Instance>(
+        target_runner: &impl TargetRunner<I>,
+        instances: &[I],
+        instance_names: Option<&[String]>,
+        scenario: &Scenario,
+        param_space: &ParamSpace,
+        num_samples: usize,
+        time_limit: Option<Duration>,
+    ) -> eyre::Result<Self> {
+        ensure!(num_samples > 0, "at least one sample run is required");
+        ensure!(!instances.is_empty(), "at least one instance is required");
+        if let Some(names) = instance_names {
+            ensure!(
+                names.len() == instances.len(),
+                "{} instance name(s) given for {} instance(s)",
+                names.len(),
+                instances.len()
+            );
+        }
+        param_space
+            .check()
+            .wrap_err("can't sample configurations from the parameter space")?;
+
+        catch_panics("on_tuning_start", || {
+            target_runner.on_tuning_start(scenario)
+        })
+        .wrap_err("failed to start the sample runs")?;
+        let total = Self::sample_runs(
+            target_runner,
+            instances,
+            instance_names,
+            scenario,
+            param_space,
+            num_samples,
+        );
+        // The tuning is ended even if a sample run failed.
+        let tuning_end = catch_panics("on_tuning_end", || target_runner.on_tuning_end(scenario))
+            .wrap_err("failed to end the sample runs");
+        let total = total?;
+        tuning_end?;
+
+        Ok(Self::from_experiment_time(
+            scenario,
+            param_space,
+            instances.len(),
+            total / num_samples as u32,
+            time_limit,
+        ))
+    }
+
+    /// Executes the sample runs of [`from_sample_runs`] and returns their total duration.
+    ///
+    /// [`from_sample_runs`]: Self::from_sample_runs
+    fn sample_runs<I: Instance>(
+        target_runner: &impl TargetRunner<I>,
+        instances: &[I],
+        instance_names: Option<&[String]>,
+        scenario: &Scenario,
+        param_space: &ParamSpace,
+        num_samples: usize,
+    ) -> eyre::Result<Duration> {
+        let mut rng = match scenario.seed {
+            Some(seed) => StdRng::seed_from_u64(seed as u64),
+            None => StdRng::from_entropy(),
+        };
+
+        let mut flat_space = param_space.clone();
+        flat_space.flatten();
+
+        let mut total = Duration::ZERO;
+        for i in 0..num_samples {
+            let index = i % instances.len();
+            let instance = &instances[index];
+            // The instances are used in order, so each one is new in the first cycle.
+            if i < instances.len() {
+                catch_panics("on_new_instance", || {
+                    target_runner.on_new_instance(scenario, instance)
+                })
+                .wrap_err_with(|| format!("failed to prepare instance {index}"))?;
+            }
+
+            let instance_name = instance_names.map(|names| names[index].clone());
+            let seed = rng.gen();
+            let values = param_space.sample_values(&mut rng);
+            let experiment = Experiment {
+                id: format!("sample-{i}"),
+                seed,
+                instance_id: Some(instance_name.clone().unwrap_or_else(|| index.to_string())),
+                instance,
+                instance_index: index,
+                instance_name,
+                params: to_params(&values, &flat_space),
+                values,
+                cancellation: CancellationToken::new(),
+                context: RaceContext::default(),
+            };
+
+            let start = Instant::now();
+            run_catching_panics(target_runner, scenario, experiment)
+                .wrap_err_with(|| format!("sample run {i} on instance {index} failed"))?;
+            total += start.elapsed();
+        }
+        Ok(total)
+    }
+}
@@ -0,0 +1,551 @@
+//! Reading and writing `irace` scenario files (`scenario.txt`).
+
+use std::{fmt::Write as _, path::Path};
+
+use eyre::{bail, eyre, WrapErr};
+
+use crate::scenario::{Scenario, TestType, Verbosity};
+
+/// A key of a scenario file which has no equivalent in [`Scenario`].
+#[derive(Debug, Clone)]
+pub struct UnsupportedKey {
+    /// The (1-based) line the key appears on.
+    pub line: usize,
+    /// The name of the key, e.g. `targetRunner`.
+    pub key: String,
+    /// The raw value as written in the file.
+    pub value: String,
+}
+
+/// The result of parsing a scenario file.
+#[derive(Debug, Clone)]
+pub struct ParsedScenario {
+    /// The scenario built from all supported keys.
+    pub scenario: Scenario,
+    /// All keys which could not be mapped to the scenario.
+    pub unsupported: Vec<UnsupportedKey>,
+}
+
+/// The scenario file keys which can be mapped to [`Scenario`].
+const SUPPORTED_KEYS: &[&str] = &[
+    "maxExperiments",
+    "minExperiments",
+    "firstTest",
+    "eachTest",
+    "testType",
+    "confidence",
+    "elitist",
+    "deterministic",
+    "sampleInstances",
+    "blockSize",
+    "logFile",
+    "execDir",
+    "parallel",
+    "seed",
+    "debugLevel",
+];
+
+/// A value in the R syntax used by scenario files.
+#[derive(Debug, Clone, PartialEq)]
+enum RValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl RValue {
+    fn parse(raw: &str) -> eyre::Result<Self> {
+        let raw = raw.trim();
+        let value = match raw {
+            "NULL" | "NA" => RValue::Null,
+            "TRUE" | "T" => RValue::Bool(true),
+            "FALSE" | "F" => RValue::Bool(false),
+            _ if raw.len() >= 2
+                && (raw.starts_with('"') && raw.ends_with('"')
+                    || raw.starts_with('\'') && raw.ends_with('\'')) =>
+            {
+                RValue::String(unescape(&raw[1..raw.len() - 1]))
+            }
+            _ => {
+                // R integer literals may be suffixed with `L`.
+                let number = raw.strip_suffix('L').unwrap_or(raw);
+                RValue::Number(
+                    number
+                        .parse::<f64>()
+                        .map_err(|_| eyre!("invalid value `{raw}`"))?,
+                )
+            }
+        };
+        Ok(value)
+    }
+
+    fn into_bool(self) -> eyre::Result<Option<bool>> {
+        match self {
+            RValue::Null => Ok(None),
+            RValue::Bool(value) => Ok(Some(value)),
+            RValue::Number(value) if value == 0.0 || value == 1.0 => Ok(Some(value == 1.0)),
+            other => bail!("expected a boolean, found {other:?}"),
+        }
+    }
+
+    fn into_u32(self) -> eyre::Result<Option<u32>> {
+        match self {
+            RValue::Null => Ok(None),
+            RValue::Number(value)
+                if value >= 0.0 && value <= u32::MAX as f64 && value.fract() == 0.0 =>
+            {
+                Ok(Some(value as u32))
+            }
+            other => bail!("expected a non-negative integer, found {other:?}"),
+        }
+    }
+
+    fn into_f64(self) -> eyre::Result<Option<f64>> {
+        match self {
+            RValue::Null => Ok(None),
+            RValue::Number(value) => Ok(Some(value)),
+            other => bail!("expected a number, found {other:?}"),
+        }
+    }
+
+    fn into_string(self) -> eyre::Result<Option<String>> {
+        match self {
+            RValue::Null => Ok(None),
+            RValue::String(value) => Ok(Some(value)),
+            other => bail!("expected a string, found {other:?}"),
+        }
+    }
+}
+
+/// Removes a trailing comment (`# ...`) which is not part of a string literal.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (Some(_), _) if escaped => escaped = false,
+            (Some(_), '\\') => escaped = true,
+            (Some(q), c) if c == q => quote = None,
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            if let Some(next) = chars.next() {
+                result.push(next);
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn r_bool(value: bool) -> &'static str {
+    if value {
+        "TRUE"
+    } else {
+        "FALSE"
+    }
+}
+
+impl Scenario {
+    /// Sets the field corresponding to `key`, returning `false` if the key is not supported.
+    fn apply_key(&mut self, key: &str, raw_value: &str) -> eyre::Result<bool> {
+        if !SUPPORTED_KEYS.contains(&key) {
+            return Ok(false);
+        }
+
+        let value = RValue::parse(raw_value)?;
+        match key {
+            "maxExperiments" => self.max_experiments = value.into_u32()?,
+            "minExperiments" => self.min_experiments = value.into_u32()?,
+            "firstTest" => self.first_test = value.into_u32()?.unwrap_or(self.first_test),
+            "eachTest" => self.each_test = value.into_u32()?.unwrap_or(self.each_test),
+            "testType" => {
+                if let Some(name) = value.into_string()? {
+                    self.test_type = TestType::from_name(&name)
+                        .ok_or_else(|| eyre!("unknown test type `{name}`"))?;
+                }
+            }
+            "confidence" => self.confidence = value.into_f64()?.unwrap_or(self.confidence),
+            "elitist" => self.elitist = value.into_bool()?.unwrap_or(self.elitist),
+            "deterministic" => {
+                self.deterministic = value.into_bool()?.unwrap_or(self.deterministic)
+            }
+            "sampleInstances" => {
+                self.sample_instances = value.into_bool()?.unwrap_or(self.sample_instances)
+            }
+            "blockSize" => {
+                self.block_size = value
+                    .into_u32()?
+                    .map_or(self.block_size, |block_size| block_size as usize)
+            }
+            "logFile" => self.log_file = value.into_string()?.map(Into::into),
+            "execDir" => self.exec_dir = value.into_string()?.map(Into::into),
+            "parallel" => {
+                self.num_jobs = value
+                    .into_u32()?
+                    .map_or(self.num_jobs, |num_jobs| num_jobs.max(1) as usize)
+            }
+            "seed" => self.seed = value.into_u32()?,
+            "debugLevel" => {
+                self.verbose = value
+                    .into_u32()?
+                    .map(Verbosity::from_level)
+                    .unwrap_or(self.verbose)
+            }
+            _ => unreachable!("unhandled supported key `{key}`"),
+        }
+        Ok(true)
+    }
+
+    /// Parses a scenario from the `irace` scenario file syntax.
+    ///
+    /// Keys without an equivalent in [`Scenario`] are collected in [`ParsedScenario::unsupported`].
+    /// To reject them instead, use [`from_scenario_str`].
+    ///
+    /// [`from_scenario_str`]: Self::from_scenario_str
+    pub fn parse_scenario(contents: &str) -> eyre::Result<ParsedScenario> {
+        let mut scenario = Scenario::builder().build();
+        let mut unsupported = Vec::new();
+
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index + 1;
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (key, raw_value) = line
+                .split_once('=')
+                .ok_or_else(|| eyre!("line {line_number}: expected `key = value`"))?;
+            let key = key.trim();
+            let raw_value = raw_value.trim();
+
+            let supported = scenario
+                .apply_key(key, raw_value)
+                .wrap_err_with(|| format!("line {line_number}: invalid value for `{key}`"))?;
+
+            if !supported {
+                unsupported.push(UnsupportedKey {
+                    line: line_number,
+                    key: key.to_owned(),
+                    value: raw_value.to_owned(),
+                });
+            }
+        }
+
+        Ok(ParsedScenario {
+            scenario,
+            unsupported,
+        })
+    }
+
+    /// Parses a scenario from the `irace` scenario file syntax.
+    ///
+    /// Returns an error listing all keys that are not supported by [`Scenario`].
+    pub fn from_scenario_str(contents: &str) -> eyre::Result<Self> {
+        let ParsedScenario {
+            scenario,
+            unsupported,
+        } = Self::parse_scenario(contents)?;
+
+        if !unsupported.is_empty() {
+            let keys = unsupported
+                .iter()
+                .map(|key| format!("`{}` (line {})", key.key, key.line))
+                .collect::<Vec<_>>()
+                .join(", ");
+            bail!("unsupported scenario keys: {keys}");
+        }
+
+        Ok(scenario)
+    }
+
+    /// Reads a scenario from an `irace` scenario file.
+    ///
+    /// See [`from_scenario_str`] for details.
+    ///
+    /// [`from_scenario_str`]: Self::from_scenario_str
+    pub fn from_scenario_file(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read scenario file {}", path.display()))?;
+        Self::from_scenario_str(&contents)
+            .wrap_err_with(|| format!("failed to parse scenario file {}", path.display()))
+    }
+
+    /// Returns the settings which differ from their default but have no equivalent
+    /// in scenario files, as pairs of field name and value.
+    pub fn unexported_fields(&self) -> Vec<(&'static str, String)> {
+        let default = Scenario::builder().build();
+        let mut fields = Vec::new();
+        if self.instance_repetitions != default.instance_repetitions {
+            fields.push((
+                "instance_repetitions",
+                self.instance_repetitions.to_string(),
+            ));
+        }
+        if self.console != default.console {
+            fields.push(("console", format!("{:?}", self.console)));
+        }
+        if self.on_panic != default.on_panic {
+            fields.push(("on_panic", format!("{:?}", self.on_panic)));
+        }
+        if self.on_error != default.on_error {
+            fields.push(("on_error", format!("{:?}", self.on_error)));
+        }
+        if let Some(experiment_timeout) = self.experiment_timeout {
+            fields.push(("experiment_timeout", format!("{experiment_timeout:?}")));
+        }
+        if let Some(on_timeout) = self.on_timeout {
+            fields.push(("on_timeout", format!("{on_timeout:?}")));
+        }
+        fields
+    }
+
+    /// Renders the scenario in the `irace` scenario file syntax.
+    ///
+    /// Settings without an equivalent in scenario files are written as comments,
+    /// i.e. they are lost when reading the file, see [`unexported_fields`].
+    ///
+    /// [`unexported_fields`]: Self::unexported_fields
+    pub fn to_scenario_string(&self) -> String {
+        let mut s = String::new();
+
+        if let Some(max_experiments) = self.max_experiments {
+            writeln!(s, "maxExperiments = {max_experiments}").unwrap();
+        }
+        if let Some(min_experiments) = self.min_experiments {
+            writeln!(s, "minExperiments = {min_experiments}").unwrap();
+        }
+        writeln!(s, "firstTest = {}", self.first_test).unwrap();
+        writeln!(s, "eachTest = {}", self.each_test).unwrap();
+        writeln!(s, "testType = \"{}\"", self.test_type.name()).unwrap();
+        writeln!(s, "confidence = {}", self.confidence).unwrap();
+        writeln!(s, "elitist = {}", r_bool(self.elitist)).unwrap();
+        writeln!(s, "deterministic = {}", r_bool(self.deterministic)).unwrap();
+        writeln!(s, "sampleInstances = {}", r_bool(self.sample_instances)).unwrap();
+        writeln!(s, "blockSize = {}", self.block_size).unwrap();
+        if let Some(log_file) = &self.log_file {
+            writeln!(s, "logFile = \"{}\"", escape(&log_file.to_string_lossy())).unwrap();
+        }
+        if let Some(exec_dir) = &self.exec_dir {
+            writeln!(s, "execDir = \"{}\"", escape(&exec_dir.to_string_lossy())).unwrap();
+        }
+        writeln!(s, "parallel = {}", self.num_jobs).unwrap();
+        if let Some(seed) = self.seed {
+            writeln!(s, "seed = {seed}").unwrap();
+        }
+        writeln!(s, "debugLevel = {}", self.verbose as u32).unwrap();
+
+        let unexported = self.unexported_fields();
+        if !unexported.is_empty() {
+            writeln!(s, "\n# Settings without an equivalent in scenario files:").unwrap();
+            for (field, value) in unexported {
+                writeln!(s, "# {field} = {value}").unwrap();
+            }
+        }
+
+        s
+    }
+
+    /// Writes the scenario to an `irace` scenario file.
+    pub fn write_scenario_file(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        let path = path.as_ref();
+        std::fs::write(path, self.to_scenario_string())
+            .wrap_err_with(|| format!("failed to write scenario file {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::scenario::{PanicPolicy, Penalty, TimeoutPolicy};
+
+    #[test]
+    fn parses_values() {
+        assert_eq!(RValue::parse("NULL").unwrap(), RValue::Null);
+        assert_eq!(RValue::parse("NA").unwrap(), RValue::Null);
+        assert_eq!(RValue::parse("T").unwrap(), RValue::Bool(true));
+        assert_eq!(RValue::parse("FALSE").unwrap(), RValue::Bool(false));
+        assert_eq!(RValue::parse("42L").unwrap(), RValue::Number(42.0));
+        assert_eq!(RValue::parse("1e3").unwrap(), RValue::Number(1000.0));
+        assert_eq!(
+            RValue::parse(r#""a \"b\"""#).unwrap(),
+            RValue::String(r#"a "b""#.to_owned())
+        );
+        assert_eq!(
+            RValue::parse("'single'").unwrap(),
+            RValue::String("single".to_owned())
+        );
+        assert!(RValue::parse("abc").is_err());
+    }
+
+    #[test]
+    fn strips_comments_outside_of_strings() {
+        assert_eq!(strip_comment("seed = 1 # comment"), "seed = 1 ");
+        assert_eq!(
+            strip_comment(r#"logFile = "a#b" # comment"#),
+            r#"logFile = "a#b" "#
+        );
+        assert_eq!(
+            strip_comment(r##"execDir = "a\"#b""##),
+            r##"execDir = "a\"#b""##
+        );
+        assert_eq!(strip_comment("# only a comment"), "");
+    }
+
+    #[test]
+    fn parses_scenario() {
+        let parsed = Scenario::parse_scenario(
+            r#"
+            ## A scenario file.
+            maxExperiments = 2000
+            firstTest = 6 # before the first test
+            eachTest = 2
+            testType = "t.holm"
+            confidence = 0.99
+            elitist = FALSE
+            logFile = "irace.Rdata"
+            parallel = 4
+            seed = 123
+            targetRunner = "./target-runner"
+            "#,
+        )
+        .unwrap();
+
+        let scenario = parsed.scenario;
+        assert_eq!(scenario.max_experiments, Some(2000));
+        assert_eq!(scenario.min_experiments, None);
+        assert_eq!(scenario.first_test, 6);
+        assert_eq!(scenario.each_test, 2);
+        assert_eq!(scenario.test_type, TestType::TTestHolm);
+        assert_eq!(scenario.confidence, 0.99);
+        assert!(!scenario.elitist);
+        assert_eq!(scenario.log_file, Some("irace.Rdata".into()));
+        assert_eq!(scenario.num_jobs, 4);
+        assert_eq!(scenario.seed, Some(123));
+
+        assert_eq!(parsed.unsupported.len(), 1);
+        assert_eq!(parsed.unsupported[0].key, "targetRunner");
+        assert_eq!(parsed.unsupported[0].value, r#""./target-runner""#);
+        assert_eq!(parsed.unsupported[0].line, 12);
+    }
+
+    #[test]
+    fn rejects_invalid_scenarios() {
+        assert!(Scenario::parse_scenario("maxExperiments").is_err());
+        assert!(Scenario::parse_scenario("maxExperiments = -1").is_err());
+        assert!(Scenario::parse_scenario("elitist = 2").is_err());
+        assert!(Scenario::parse_scenario(r#"testType = "unknown""#).is_err());
+        assert!(Scenario::from_scenario_str(r#"targetRunner = "./target-runner""#).is_err());
+    }
+
+    #[test]
+    fn round_trips_scenario() {
+        let scenario = Scenario::builder()
+            .max_experiments(1000)
+            .min_experiments(500)
+            .first_test(10)
+            .each_test(2)
+            .test_type(TestType::TTestBonferroni)
+            .confidence(0.9)
+            .elitist(false)
+            .deterministic(true)
+            .sample_instances(false)
+            .block_size(2)
+            .log_file(r#"logs/"quoted"\irace.Rdata"#)
+            .exec_dir("exec dir")
+            .num_jobs(3)
+            .seed(7)
+            .verbose(Verbosity::Standard)
+            .build();
+
+        let parsed = Scenario::from_scenario_str(&scenario.to_scenario_string()).unwrap();
+        assert_eq!(parsed.max_experiments, scenario.max_experiments);
+        assert_eq!(parsed.min_experiments, scenario.min_experiments);
+        assert_eq!(parsed.first_test, scenario.first_test);
+        assert_eq!(parsed.each_test, scenario.each_test);
+        assert_eq!(parsed.test_type, scenario.test_type);
+        assert_eq!(parsed.confidence, scenario.confidence);
+        assert_eq!(parsed.elitist, scenario.elitist);
+        assert_eq!(parsed.deterministic, scenario.deterministic);
+        assert_eq!(parsed.sample_instances, scenario.sample_instances);
+        assert_eq!(parsed.block_size, scenario.block_size);
+        assert_eq!(parsed.log_file, scenario.log_file);
+        assert_eq!(parsed.exec_dir, scenario.exec_dir);
+        assert_eq!(parsed.num_jobs, scenario.num_jobs);
+        assert_eq!(parsed.seed, scenario.seed);
+        assert_eq!(parsed.verbose as u32, scenario.verbose as u32);
+    }
+
+    #[test]
+    fn writes_unexported_fields_as_comments() {
+        assert!(Scenario::builder().build().unexported_fields().is_empty());
+
+        let scenario = Scenario::builder()
+            .max_experiments(100)
+            .instance_repetitions(3)
+            .on_panic(PanicPolicy::Penalty(Penalty::Fixed(1e9)))
+            .experiment_timeout(Duration::from_secs(10))
+            .on_timeout(TimeoutPolicy::Censored)
+            .build();
+        let fields: Vec<_> = scenario
+            .unexported_fields()
+            .into_iter()
+            .map(|(field, _)| field)
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                "instance_repetitions",
+                "on_panic",
+                "experiment_timeout",
+                "on_timeout"
+            ]
+        );
+
+        let contents = scenario.to_scenario_string();
+        assert!(contents.contains("# instance_repetitions = 3\n"));
+        let parsed = Scenario::parse_scenario(&contents).unwrap();
+        assert!(parsed.unsupported.is_empty());
+        assert_eq!(parsed.scenario.instance_repetitions, 1);
+    }
+
+    #[test]
+    fn null_values_keep_defaults() {
+        let scenario = Scenario::from_scenario_str("blockSize = NULL\nparallel = NA").unwrap();
+        assert_eq!(scenario.block_size, 1);
+        assert_eq!(scenario.num_jobs, 1);
+    }
+
+    #[test]
+    fn accepts_test_type_aliases() {
+        for (name, test_type) in [
+            ("friedman", TestType::FTest),
+            ("t.none", TestType::TTest),
+            ("t.holm", TestType::TTestHolm),
+            ("t.bonferroni", TestType::TTestBonferroni),
+        ] {
+            assert_eq!(TestType::from_name(name), Some(test_type));
+            assert_eq!(TestType::from_name(test_type.name()), Some(test_type));
+        }
+    }
+}
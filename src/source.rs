@@ -0,0 +1,219 @@
+use std::{
+    any::Any,
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use eyre::{eyre, WrapErr};
+
+use crate::runner::{catch_panics, Instance};
+
+/// Trait representing a source of problem instances which are loaded on demand.
+///
+/// In contrast to passing all instances up front, only the instances required by the
+/// currently running experiments are kept in memory,
+/// see [`Run::with_instance_source`](crate::Run::with_instance_source).
+///
+/// Errors while loading an instance fail the experiment,
+/// which is handled according to [`Scenario::on_error`](crate::scenario::Scenario::on_error).
+pub trait InstanceSource<I>: Send + Sync + 'static {
+    /// Returns the number of instances.
+    fn len(&self) -> usize;
+
+    /// Returns if the source contains no instances.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the stable name of the instance at `index`.
+    fn name(&self, index: usize) -> String;
+
+    /// Loads the instance at `index`.
+    ///
+    /// A panic is treated like an error, i.e. it fails the experiments on the instance.
+    fn load(&self, index: usize) -> eyre::Result<I>;
+}
+
+/// An [`InstanceSource`] loading instances from files, e.g. all files of a directory
+/// or the files listed in a manifest.
+///
+/// The instances are named by their path relative to the directory or manifest.
+pub struct FileInstances<F> {
+    root: PathBuf,
+    files: Vec<PathBuf>,
+    loader: F,
+}
+
+impl<F> FileInstances<F> {
+    /// Constructs a new `FileInstances` from all files in the directory `dir`,
+    /// optionally restricted to files with the given `extension`.
+    ///
+    /// The files are sorted by name, and loaded using the `loader`.
+    pub fn from_directory(
+        dir: impl AsRef<Path>,
+        extension: Option<&str>,
+        loader: F,
+    ) -> eyre::Result<Self> {
+        let root = dir.as_ref().to_owned();
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&root)
+            .wrap_err_with(|| format!("failed to read instance directory {}", root.display()))?
+        {
+            let path = entry?.path();
+            let matches = extension.map_or(true, |extension| {
+                path.extension().is_some_and(|ext| ext == extension)
+            });
+            if path.is_file() && matches {
+                files.push(path.strip_prefix(&root)?.to_owned());
+            }
+        }
+        files.sort();
+
+        Ok(Self {
+            root,
+            files,
+            loader,
+        })
+    }
+
+    /// Constructs a new `FileInstances` from the files listed in the manifest at `path`.
+    ///
+    /// The manifest contains one path per line, relative to the directory of the manifest.
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn from_manifest(path: impl AsRef<Path>, loader: F) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let manifest = fs::read_to_string(path)
+            .wrap_err_with(|| format!("failed to read instance manifest {}", path.display()))?;
+
+        let root = path.parent().map(Path::to_owned).unwrap_or_default();
+        let files = manifest
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(PathBuf::from)
+            .collect();
+
+        Ok(Self {
+            root,
+            files,
+            loader,
+        })
+    }
+}
+
+impl<I, F> InstanceSource<I> for FileInstances<F>
+where
+    F: Fn(&Path) -> eyre::Result<I> + Send + Sync + 'static,
+{
+    fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    fn name(&self, index: usize) -> String {
+        self.files[index].to_string_lossy().into_owned()
+    }
+
+    fn load(&self, index: usize) -> eyre::Result<I> {
+        let path = self.root.join(&self.files[index]);
+        (self.loader)(&path).wrap_err_with(|| format!("failed to load {}", path.display()))
+    }
+}
+
+/// A subset of the instances of another [`InstanceSource`].
+pub(crate) struct InstanceSubset<I> {
+    pub source: Arc<dyn InstanceSource<I>>,
+    /// The indices of the instances in the subset.
+    pub indices: Vec<usize>,
+}
+
+impl<I: Instance> InstanceSource<I> for InstanceSubset<I> {
+    fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn name(&self, index: usize) -> String {
+        self.source.name(self.indices[index])
+    }
+
+    fn load(&self, index: usize) -> eyre::Result<I> {
+        self.source.load(self.indices[index])
+    }
+}
+
+/// A type-erased collection of instances, which are either in memory or loaded on demand.
+pub(crate) trait ErasedInstances: Send + Sync + 'static {
+    fn len(&self) -> usize;
+
+    fn load(&self, index: usize) -> eyre::Result<Arc<dyn Any + Send + Sync>>;
+}
+
+impl<I: Instance> ErasedInstances for Vec<Arc<I>> {
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+
+    fn load(&self, index: usize) -> eyre::Result<Arc<dyn Any + Send + Sync>> {
+        let instance = self
+            .get(index)
+            .ok_or_else(|| eyre!("instance index {index} is out of range"))?;
+        Ok(instance.clone())
+    }
+}
+
+/// Loads instances from an [`InstanceSource`], keeping the most recently used instances in memory.
+pub(crate) struct InstanceLoader<I> {
+    source: Arc<dyn InstanceSource<I>>,
+    capacity: usize,
+    /// The cached instances, from least to most recently used.
+    cache: Mutex<VecDeque<(usize, Arc<I>)>>,
+}
+
+impl<I: Instance> InstanceLoader<I> {
+    /// Constructs a new `InstanceLoader` caching at most `capacity` instances, but at least one.
+    pub fn new(source: Arc<dyn InstanceSource<I>>, capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            source,
+            capacity,
+            cache: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Returns the instance at `index`, loading it if it is not cached.
+    ///
+    /// The cache is not locked while loading, so instances may be loaded concurrently.
+    fn load_cached(&self, index: usize) -> eyre::Result<Arc<I>> {
+        {
+            let mut cache = self.cache.lock().unwrap();
+            if let Some(position) = cache.iter().position(|(cached, _)| *cached == index) {
+                let entry = cache.remove(position).unwrap();
+                let instance = entry.1.clone();
+                cache.push_back(entry);
+                return Ok(instance);
+            }
+        }
+
+        let instance = Arc::new(catch_panics("instance source", || self.source.load(index))?);
+
+        let mut cache = self.cache.lock().unwrap();
+        if !cache.iter().any(|(cached, _)| *cached == index) {
+            if cache.len() == self.capacity {
+                cache.pop_front();
+            }
+            cache.push_back((index, instance.clone()));
+        }
+        Ok(instance)
+    }
+}
+
+impl<I: Instance> ErasedInstances for InstanceLoader<I> {
+    fn len(&self) -> usize {
+        self.source.len()
+    }
+
+    fn load(&self, index: usize) -> eyre::Result<Arc<dyn Any + Send + Sync>> {
+        Ok(self.load_cached(index)?)
+    }
+}